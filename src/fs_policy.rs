@@ -0,0 +1,89 @@
+//! Which files scripts may read and write
+
+use std::path::{Component, Path, PathBuf};
+
+/// Restricts the files scripts can access to the session cwd and a list of
+/// extra roots
+#[derive(Clone, Debug, Default)]
+pub struct FsPolicy {
+    /// Directories outside the session cwd that scripts may also access
+    extra_roots: Vec<PathBuf>,
+    /// Let scripts access any file the agent process can
+    unrestricted: bool,
+}
+
+impl FsPolicy {
+    /// Only allow access inside the session cwd
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow access to any file the agent process can access
+    pub fn unrestricted() -> Self {
+        Self {
+            unrestricted: true,
+            ..Self::default()
+        }
+    }
+
+    /// Also allow access inside `root`
+    pub fn allow_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.extra_roots.push(root.into());
+        self
+    }
+
+    /// Check that a script running in `cwd` may access `path`. Symlinks and
+    /// `..` components are resolved first, so neither can lead out of the
+    /// allowed roots.
+    pub(crate) fn check(&self, cwd: &Path, path: &Path) -> Result<(), String> {
+        if self.unrestricted {
+            return Ok(());
+        }
+
+        let allowed = resolve(path).is_some_and(|path| {
+            std::iter::once(cwd)
+                .chain(self.extra_roots.iter().map(PathBuf::as_path))
+                .filter_map(resolve)
+                .any(|root| path.starts_with(root))
+        });
+        if allowed {
+            Ok(())
+        } else {
+            Err(format!(
+                "Access denied to {}: outside the session cwd and allowed roots",
+                path.display()
+            ))
+        }
+    }
+}
+
+/// The absolute path `path` refers to with symlinks and `..` resolved, or
+/// `None` if that cannot be determined. `path` does not have to exist.
+fn resolve(path: &Path) -> Option<PathBuf> {
+    let path = std::path::absolute(path).ok()?;
+    let mut existing = path.as_path();
+    // The components after `existing`, innermost first
+    let mut missing = Vec::new();
+    loop {
+        match existing.canonicalize() {
+            Ok(resolved) => {
+                return Some(
+                    missing
+                        .iter()
+                        .rev()
+                        .fold(resolved, |path, name| path.join(name)),
+                );
+            }
+            // A dangling symlink could point anywhere once its target is created
+            Err(_) if existing.symlink_metadata().is_ok() => return None,
+            Err(_) => {
+                // `..` after a missing directory cannot be resolved
+                match existing.components().next_back()? {
+                    Component::Normal(name) => missing.push(name),
+                    _ => return None,
+                }
+                existing = existing.parent()?;
+            }
+        }
+    }
+}
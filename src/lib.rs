@@ -1,61 +1,292 @@
+mod fs_policy;
+mod mcp_error;
 mod mcp_module;
+mod mcp_pool;
+mod permissions;
+mod plan_module;
+mod process_module;
+mod session_store;
+mod sse_transport;
+mod terminal_module;
 
 use anyhow::Result;
-use mcp_module::McpModule;
-use rhai::{Engine, Module};
+use mcp_module::{McpModule, json_to_dynamic};
+use mcp_pool::McpClientPool;
+use permissions::Decision;
+use plan_module::PlanModule;
+use process_module::ProcessModule;
+use rhai::{AST, Dynamic, Engine, EvalAltResult, Module, Scope};
 use sacp::schema::{
-    AgentCapabilities, ContentBlock, ContentChunk, InitializeRequest, InitializeResponse,
-    LoadSessionRequest, LoadSessionResponse, McpServer, NewSessionRequest, NewSessionResponse,
-    PromptRequest, PromptResponse, SessionId, SessionNotification, SessionUpdate, StopReason,
-    TextContent, ToolCallLocation, ToolCallStatus, ToolCallUpdate, ToolCallUpdateFields,
+    AgentCapabilities, CancelNotification, ClientCapabilities, ContentBlock, ContentChunk,
+    CreateTerminalRequest, Diff, EmbeddedResource, EmbeddedResourceResource, EnvVariable,
+    InitializeRequest, InitializeResponse, KillTerminalCommandRequest, LoadSessionRequest,
+    LoadSessionResponse, McpCapabilities, McpServer, NewSessionRequest, NewSessionResponse,
+    PermissionOption, PermissionOptionKind, Plan, PlanEntry, PlanEntryStatus, PromptCapabilities,
+    PromptRequest, PromptResponse, ReadTextFileRequest, ReleaseTerminalRequest,
+    RequestPermissionOutcome, RequestPermissionRequest, SessionId, SessionNotification,
+    SessionUpdate, StopReason, Terminal, TerminalOutputRequest, TerminalOutputResponse,
+    TextContent, TextResourceContents, ToolCall, ToolCallContent, ToolCallId, ToolCallLocation,
+    ToolCallStatus, ToolCallUpdate, ToolCallUpdateFields, ToolKind, WaitForTerminalExitRequest,
+    WriteTextFileRequest,
 };
 use sacp::{AgentToClient, Component, JrConnectionCx, JrRequestCx};
-use std::collections::HashMap;
+use session_store::StoredSession;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use terminal_module::TerminalModule;
 use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+pub use fs_policy::FsPolicy;
+pub use mcp_error::{McpError, McpErrorKind};
+pub use permissions::PermissionPolicy;
+pub use session_store::SessionStore;
+
+/// How long MCP requests may take unless the server or script says otherwise
+pub const DEFAULT_MCP_TIMEOUT: Duration = Duration::from_secs(60);
 
 /// Messages sent from Rhai execution to the async runtime
 pub enum RhaiMessage {
     /// Send text to the client via `say()`
     Say(String),
-    /// List tools from an MCP server
+    /// Send a thought to the client via `think()`
+    Think(String),
+    /// Ask the user `question` via `confirm()` or `choose()`, responding with
+    /// the id of the option they selected
+    Ask {
+        question: String,
+        options: Vec<PermissionOption>,
+        response_tx: std::sync::mpsc::Sender<Result<String, String>>,
+    },
+    /// Replace the session's plan via `plan::set()`
+    SetPlan(Vec<PlanEntry>),
+    /// Change the status of one entry of the session's plan
+    UpdatePlanEntry {
+        index: usize,
+        status: PlanEntryStatus,
+        response_tx: std::sync::mpsc::Sender<Result<(), String>>,
+    },
+    /// List tools from an MCP server, each described by a JSON object
     ListTools {
         server: String,
-        response_tx: std::sync::mpsc::Sender<Result<Vec<String>, String>>,
+        /// Overrides the server's request timeout
+        timeout: Option<Duration>,
+        response_tx: std::sync::mpsc::Sender<Result<Vec<serde_json::Value>, McpError>>,
     },
-    /// Call an MCP tool
+    /// Call an MCP tool, responding with the result described as a JSON object
     CallTool {
         server: String,
         tool: String,
         args: serde_json::Value,
+        /// Overrides the server's request timeout
+        timeout: Option<Duration>,
+        response_tx: std::sync::mpsc::Sender<Result<serde_json::Value, McpError>>,
+    },
+    /// Write a file on disk, responding once the write has finished
+    WriteFile {
+        path: String,
+        content: String,
+        response_tx: std::sync::mpsc::Sender<Result<(), String>>,
+    },
+    /// Read a text file, optionally only `limit` lines starting at the 1-based `line`
+    ReadFile {
+        path: String,
+        line: Option<u32>,
+        limit: Option<u32>,
+        response_tx: std::sync::mpsc::Sender<Result<String, String>>,
+    },
+    /// Run a command in a client terminal, responding with its exit status and
+    /// output described as a JSON object once it exits
+    RunInTerminal {
+        command: String,
+        args: Vec<String>,
+        /// Relative to the session cwd, which is the default
+        cwd: Option<String>,
+        env: Vec<(String, String)>,
+        output_byte_limit: Option<u64>,
+        response_tx: std::sync::mpsc::Sender<Result<serde_json::Value, String>>,
+    },
+    /// Run a local process, responding with its exit code and output described
+    /// as a JSON object once it exits
+    Exec {
+        command: String,
+        args: Vec<String>,
+        /// Relative to the session cwd, which is the default
+        cwd: Option<String>,
+        env: Vec<(String, String)>,
+        /// Written to the process's standard input, which is closed afterwards
+        stdin: Option<String>,
+        /// Kill the process if it runs longer
+        timeout: Option<Duration>,
         response_tx: std::sync::mpsc::Sender<Result<serde_json::Value, String>>,
     },
-    /// Write a file on disk
-    WriteFile { path: String, content: String },
+}
+
+/// Rhai state that persists across prompts in the same session
+#[derive(Default)]
+struct ScriptState {
+    /// Variables defined by earlier prompts
+    scope: Scope<'static>,
+    /// Functions defined by earlier prompts
+    functions: AST,
 }
 
 /// Session data for each active session
 struct SessionData {
+    /// Working directory the client gave for the session; relative file paths
+    /// used by scripts are resolved against it
+    cwd: PathBuf,
     mcp_servers: Vec<McpServer>,
+    /// Connections to the servers in `mcp_servers`, reused across prompts
+    mcp_clients: Arc<McpClientPool>,
+    script_state: Arc<Mutex<ScriptState>>,
+    /// Cancelled when the client sends `session/cancel` for the running prompt
+    cancel_token: CancellationToken,
+    /// Every update sent for this session, replayed on `session/load`
+    transcript: Vec<SessionUpdate>,
+    /// Actions the user chose to always allow, which are not asked about again
+    always_allowed: HashSet<String>,
+    /// Whether `mcp_servers` changed while a prompt was using `mcp_clients`,
+    /// so the connections must be replaced once it finishes
+    stale_mcp_clients: bool,
+    /// The entries of the last plan sent to the client, which is resent in
+    /// full whenever an entry changes
+    plan: Vec<PlanEntry>,
+}
+
+impl SessionData {
+    /// Close the connections to the session's old MCP servers, unless a
+    /// prompt is still using them
+    fn replace_stale_mcp_clients(&mut self) {
+        // Running prompts hold their own reference to the pool
+        if !self.stale_mcp_clients || Arc::strong_count(&self.mcp_clients) > 1 {
+            return;
+        }
+        self.stale_mcp_clients = false;
+        let old_clients = std::mem::take(&mut self.mcp_clients);
+        tokio::spawn(async move { old_clients.shutdown().await });
+    }
 }
 
 /// Rhai scripting ACP agent
 #[derive(Clone)]
 pub struct RhaiAgent {
     sessions: Arc<Mutex<HashMap<SessionId, SessionData>>>,
+    session_store: Option<SessionStore>,
+    mcp_timeout: Duration,
+    /// Which files scripts may read and write
+    fs_policy: Arc<FsPolicy>,
+    /// When to ask the client before scripts have side effects
+    permission_policy: PermissionPolicy,
+    /// Executables scripts may run locally with `process::exec`
+    allowed_commands: Arc<HashSet<String>>,
+    /// What the client said it supports in its `initialize` request
+    client_capabilities: Arc<Mutex<ClientCapabilities>>,
 }
 
 impl RhaiAgent {
     pub fn new() -> Self {
         Self {
             sessions: Arc::new(Mutex::new(HashMap::new())),
+            session_store: None,
+            mcp_timeout: DEFAULT_MCP_TIMEOUT,
+            fs_policy: Arc::default(),
+            permission_policy: PermissionPolicy::default(),
+            allowed_commands: Arc::default(),
+            client_capabilities: Arc::default(),
         }
     }
 
-    fn create_session(&self, session_id: &SessionId, mcp_servers: Vec<McpServer>) {
-        let mcp_server_count = mcp_servers.len();
+    /// Save sessions to `store` so they can be restored with `session/load`
+    /// after the agent restarts
+    pub fn with_session_store(mut self, store: SessionStore) -> Self {
+        self.session_store = Some(store);
+        self
+    }
+
+    /// Give up on MCP requests that take longer than `timeout`, for servers
+    /// that do not configure their own timeout
+    pub fn with_mcp_timeout(mut self, timeout: Duration) -> Self {
+        self.mcp_timeout = timeout;
+        self
+    }
+
+    /// Restrict the files scripts may read and write to those allowed by
+    /// `policy` (by default, only files inside the session cwd)
+    pub fn with_fs_policy(mut self, policy: FsPolicy) -> Self {
+        self.fs_policy = Arc::new(policy);
+        self
+    }
+
+    /// Ask the client for permission before scripts write files, run commands
+    /// or call MCP tools, as `policy` says
+    pub fn with_permission_policy(mut self, policy: PermissionPolicy) -> Self {
+        self.permission_policy = policy;
+        self
+    }
+
+    /// Let scripts run the executables in `commands` locally with
+    /// `process::exec`. Commands must match an entry exactly, so allowing
+    /// `cargo` does not allow `/tmp/cargo`, and are found on the agent's own
+    /// `PATH` rather than one the script sets. Scripts can still set other
+    /// environment variables, so only allow commands that are safe to run
+    /// with any environment.
+    pub fn with_allowed_commands(
+        mut self,
+        commands: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.allowed_commands = Arc::new(commands.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// The timeout for a request to `server_name`: the script's override, then
+    /// the server's configured timeout, then the agent default
+    fn mcp_timeout(
+        &self,
+        mcp_servers: &[McpServer],
+        server_name: &str,
+        requested: Option<Duration>,
+    ) -> Duration {
+        requested
+            .or_else(|| mcp_pool::configured_timeout(mcp_servers, server_name))
+            .unwrap_or(self.mcp_timeout)
+    }
+
+    fn create_session(&self, session_id: &SessionId, stored: StoredSession, cwd: PathBuf) {
+        let mcp_server_count = stored.mcp_servers.len();
+        // A loaded session continues the plan it last sent
+        let plan = stored
+            .transcript
+            .iter()
+            .rev()
+            .find_map(|update| match update {
+                SessionUpdate::Plan(plan) => Some(plan.entries.clone()),
+                _ => None,
+            })
+            .unwrap_or_default();
+        let script_state = ScriptState {
+            scope: stored.restore_scope(),
+            functions: AST::empty(),
+        };
         let mut sessions = self.sessions.lock().unwrap();
-        sessions.insert(session_id.clone(), SessionData { mcp_servers });
+        sessions.insert(
+            session_id.clone(),
+            SessionData {
+                cwd,
+                mcp_servers: stored.mcp_servers,
+                mcp_clients: Arc::default(),
+                script_state: Arc::new(Mutex::new(script_state)),
+                cancel_token: CancellationToken::new(),
+                transcript: stored.transcript,
+                always_allowed: HashSet::new(),
+                stale_mcp_clients: false,
+                plan,
+            },
+        );
         tracing::info!(
             "Created session: {} with {} MCP servers",
             session_id,
@@ -63,11 +294,381 @@ impl RhaiAgent {
         );
     }
 
+    /// Capture the parts of a session that can be saved to disk. Returns
+    /// `None` while a script is running in the session, since the script holds
+    /// its state until it finishes; the prompt saves the session afterwards.
+    fn snapshot_session(&self, session_id: &SessionId) -> Option<StoredSession> {
+        // Never wait for the script state while holding `sessions`, or a
+        // running script could not send updates or be cancelled
+        let (mut stored, script_state) = {
+            let sessions = self.sessions.lock().unwrap();
+            let session = sessions.get(session_id)?;
+            let stored = StoredSession {
+                mcp_servers: session.mcp_servers.clone(),
+                transcript: session.transcript.clone(),
+                ..Default::default()
+            };
+            (stored, session.script_state.clone())
+        };
+        let Ok(state) = script_state.try_lock() else {
+            tracing::debug!(?session_id, "Script running, not saving session");
+            return None;
+        };
+        stored.capture_variables(&state.scope);
+        Some(stored)
+    }
+
+    /// Save the session to the session store, if one is configured
+    async fn save_session(&self, session_id: &SessionId) {
+        let Some(store) = &self.session_store else {
+            return;
+        };
+        let Some(stored) = self.snapshot_session(session_id) else {
+            return;
+        };
+        if let Err(e) = store.save(session_id, &stored).await {
+            tracing::warn!(?session_id, ?e, "Failed to save session");
+        }
+    }
+
+    fn record_update(&self, session_id: &SessionId, update: &SessionUpdate) {
+        let mut sessions = self.sessions.lock().unwrap();
+        if let Some(session) = sessions.get_mut(session_id) {
+            session.transcript.push(update.clone());
+        }
+    }
+
+    /// Send an update to the client, recording it in the session transcript
+    fn send_update(
+        &self,
+        cx: &JrConnectionCx<AgentToClient>,
+        session_id: &SessionId,
+        update: SessionUpdate,
+    ) -> Result<(), sacp::Error> {
+        self.record_update(session_id, &update);
+        cx.send_notification(SessionNotification::new(session_id.clone(), update))
+    }
+
+    /// Whether the user chose to always allow `action` in this session
+    fn is_always_allowed(&self, session_id: &SessionId, action: &str) -> bool {
+        let sessions = self.sessions.lock().unwrap();
+        sessions
+            .get(session_id)
+            .is_some_and(|s| s.always_allowed.contains(action))
+    }
+
+    fn allow_always(&self, session_id: &SessionId, action: &str) {
+        let mut sessions = self.sessions.lock().unwrap();
+        if let Some(session) = sessions.get_mut(session_id) {
+            session.always_allowed.insert(action.to_string());
+        }
+    }
+
+    /// Replace the session's plan, returning the plan to send to the client
+    fn set_plan(&self, session_id: &SessionId, entries: Vec<PlanEntry>) -> Plan {
+        let mut sessions = self.sessions.lock().unwrap();
+        if let Some(session) = sessions.get_mut(session_id) {
+            session.plan = entries.clone();
+        }
+        Plan::new(entries)
+    }
+
+    /// Change the status of the session's plan entry at `index`, returning the
+    /// whole plan to send to the client
+    fn update_plan_entry(
+        &self,
+        session_id: &SessionId,
+        index: usize,
+        status: PlanEntryStatus,
+    ) -> Result<Plan, String> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let Some(session) = sessions.get_mut(session_id) else {
+            return Err("Session not found".to_string());
+        };
+        let Some(entry) = session.plan.get_mut(index) else {
+            return Err(format!("The plan has no entry {}", index));
+        };
+        entry.status = status;
+        Ok(Plan::new(session.plan.clone()))
+    }
+
+    /// Ask the client whether `tool_call` may run, unless the user already
+    /// chose to always allow `action`
+    async fn permission_granted(
+        &self,
+        cx: &JrConnectionCx<AgentToClient>,
+        session_id: &SessionId,
+        action: &str,
+        tool_call: ToolCall,
+    ) -> bool {
+        if self.is_always_allowed(session_id, action) {
+            return true;
+        }
+
+        let response = cx
+            .send_request(RequestPermissionRequest::new(
+                session_id.clone(),
+                tool_call.into(),
+                permissions::permission_options(),
+            ))
+            .block_task()
+            .await;
+        match response.map(|response| permissions::decision(&response.outcome)) {
+            Ok(Decision::AllowOnce) => true,
+            Ok(Decision::AllowAlways) => {
+                self.allow_always(session_id, action);
+                true
+            }
+            Ok(Decision::Reject) => false,
+            Err(e) => {
+                tracing::warn!(?session_id, ?e, "Permission request failed");
+                false
+            }
+        }
+    }
+
+    fn get_cwd(&self, session_id: &SessionId) -> Option<PathBuf> {
+        let sessions = self.sessions.lock().unwrap();
+        sessions.get(session_id).map(|s| s.cwd.clone())
+    }
+
+    fn set_cwd(&self, session_id: &SessionId, cwd: PathBuf) {
+        let mut sessions = self.sessions.lock().unwrap();
+        if let Some(session) = sessions.get_mut(session_id) {
+            session.cwd = cwd;
+        }
+    }
+
     fn get_mcp_servers(&self, session_id: &SessionId) -> Option<Vec<McpServer>> {
         let sessions = self.sessions.lock().unwrap();
         sessions.get(session_id).map(|s| s.mcp_servers.clone())
     }
 
+    fn get_mcp_clients(&self, session_id: &SessionId) -> Option<Arc<McpClientPool>> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions.get_mut(session_id)?;
+        session.replace_stale_mcp_clients();
+        Some(session.mcp_clients.clone())
+    }
+
+    fn replace_stale_mcp_clients(&self, session_id: &SessionId) {
+        let mut sessions = self.sessions.lock().unwrap();
+        if let Some(session) = sessions.get_mut(session_id) {
+            session.replace_stale_mcp_clients();
+        }
+    }
+
+    fn set_mcp_servers(&self, session_id: &SessionId, mcp_servers: Vec<McpServer>) {
+        let mut sessions = self.sessions.lock().unwrap();
+        if let Some(session) = sessions.get_mut(session_id)
+            && session.mcp_servers != mcp_servers
+        {
+            session.mcp_servers = mcp_servers;
+            session.stale_mcp_clients = true;
+            session.replace_stale_mcp_clients();
+        }
+    }
+
+    /// Disconnect the MCP clients of every session
+    async fn shutdown_mcp_clients(&self) {
+        let pools: Vec<_> = {
+            let sessions = self.sessions.lock().unwrap();
+            sessions.values().map(|s| s.mcp_clients.clone()).collect()
+        };
+        for pool in pools {
+            pool.shutdown().await;
+        }
+    }
+
+    fn get_script_state(&self, session_id: &SessionId) -> Option<Arc<Mutex<ScriptState>>> {
+        let sessions = self.sessions.lock().unwrap();
+        sessions.get(session_id).map(|s| s.script_state.clone())
+    }
+
+    fn client_can_read_files(&self) -> bool {
+        self.client_capabilities.lock().unwrap().fs.read_text_file
+    }
+
+    fn client_can_write_files(&self) -> bool {
+        self.client_capabilities.lock().unwrap().fs.write_text_file
+    }
+
+    fn client_has_terminals(&self) -> bool {
+        self.client_capabilities.lock().unwrap().terminal
+    }
+
+    /// Run the command in `request` in a new client terminal, embedding the
+    /// terminal in the tool call `tool_call_id` so the client shows its live
+    /// output. The command is killed if the prompt is cancelled.
+    async fn run_in_terminal(
+        &self,
+        cx: &JrConnectionCx<AgentToClient>,
+        session_id: &SessionId,
+        tool_call_id: &ToolCallId,
+        request: CreateTerminalRequest,
+        cancel_token: &CancellationToken,
+    ) -> Result<TerminalOutputResponse, String> {
+        let command = request.command.clone();
+        let error = |e: sacp::Error| format!("Failed to run {}: {}", command, e);
+
+        let terminal_id = cx
+            .send_request(request)
+            .block_task()
+            .await
+            .map_err(error)?
+            .terminal_id;
+        self.send_update(
+            cx,
+            session_id,
+            SessionUpdate::ToolCallUpdate(ToolCallUpdate::new(
+                tool_call_id.clone(),
+                ToolCallUpdateFields::new()
+                    .status(ToolCallStatus::InProgress)
+                    .content(vec![ToolCallContent::Terminal(Terminal::new(
+                        terminal_id.clone(),
+                    ))]),
+            )),
+        )
+        .map_err(error)?;
+
+        let exited = tokio::select! {
+            result = cx
+                .send_request(WaitForTerminalExitRequest::new(session_id.clone(), terminal_id.clone()))
+                .block_task() => result.map(|_| ()),
+            _ = cancel_token.cancelled() => cx
+                .send_request(KillTerminalCommandRequest::new(session_id.clone(), terminal_id.clone()))
+                .block_task()
+                .await
+                .map(|_| ()),
+        };
+        let output = match exited {
+            Ok(()) => {
+                cx.send_request(TerminalOutputRequest::new(
+                    session_id.clone(),
+                    terminal_id.clone(),
+                ))
+                .block_task()
+                .await
+            }
+            Err(e) => Err(e),
+        };
+
+        // Release the terminal whatever happened; the client keeps showing
+        // the output embedded in the tool call
+        if let Err(e) = cx
+            .send_request(ReleaseTerminalRequest::new(session_id.clone(), terminal_id))
+            .block_task()
+            .await
+        {
+            tracing::warn!(?session_id, ?e, "Failed to release terminal");
+        }
+
+        output.map_err(error)
+    }
+
+    /// Read `path`, through the client when it can read files so that unsaved
+    /// editor buffers are seen
+    async fn read_text_file(
+        &self,
+        cx: &JrConnectionCx<AgentToClient>,
+        session_id: &SessionId,
+        path: &Path,
+        line: Option<u32>,
+        limit: Option<u32>,
+    ) -> Result<String, String> {
+        if self.client_can_read_files() {
+            // ACP requires absolute paths
+            let absolute_path = std::path::absolute(path)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            cx.send_request(
+                ReadTextFileRequest::new(session_id.clone(), absolute_path)
+                    .line(line)
+                    .limit(limit),
+            )
+            .block_task()
+            .await
+            .map(|response| response.content)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+        } else {
+            let content = tokio::fs::read_to_string(path)
+                .await
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            Ok(select_lines(&content, line, limit))
+        }
+    }
+
+    /// The text a write to `path` replaces, for showing the write as a diff:
+    /// `Some(None)` if the file does not exist yet, or `None` if it exists but
+    /// cannot be read as text, in which case no diff is shown
+    async fn diff_base(
+        &self,
+        cx: &JrConnectionCx<AgentToClient>,
+        session_id: &SessionId,
+        path: &Path,
+    ) -> Option<Option<String>> {
+        match tokio::fs::try_exists(path).await {
+            Ok(false) => Some(None),
+            Ok(true) => self
+                .read_text_file(cx, session_id, path, None, None)
+                .await
+                .ok()
+                .map(Some),
+            Err(_) => None,
+        }
+    }
+
+    /// Write `content` to `path`, through the client when it can write files so
+    /// that its open editor buffers see the change
+    async fn write_text_file(
+        &self,
+        cx: &JrConnectionCx<AgentToClient>,
+        session_id: &SessionId,
+        path: &Path,
+        content: String,
+    ) -> Result<(), String> {
+        if self.client_can_write_files() {
+            // ACP requires absolute paths
+            let absolute_path = std::path::absolute(path)
+                .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+            cx.send_request(WriteTextFileRequest::new(
+                session_id.clone(),
+                absolute_path,
+                content,
+            ))
+            .block_task()
+            .await
+            .map(|_| ())
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+        } else {
+            tokio::fs::write(path, content)
+                .await
+                .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+        }
+    }
+
+    fn get_transcript(&self, session_id: &SessionId) -> Option<Vec<SessionUpdate>> {
+        let sessions = self.sessions.lock().unwrap();
+        sessions.get(session_id).map(|s| s.transcript.clone())
+    }
+
+    /// Install a fresh cancellation token for a prompt that is about to run
+    fn start_prompt(&self, session_id: &SessionId) -> CancellationToken {
+        let cancel_token = CancellationToken::new();
+        let mut sessions = self.sessions.lock().unwrap();
+        if let Some(session) = sessions.get_mut(session_id) {
+            session.cancel_token = cancel_token.clone();
+        }
+        cancel_token
+    }
+
+    fn cancel_session(&self, session_id: &SessionId) {
+        let sessions = self.sessions.lock().unwrap();
+        if let Some(session) = sessions.get(session_id) {
+            tracing::info!("Cancelling prompt in session: {}", session_id);
+            session.cancel_token.cancel();
+        }
+    }
+
     async fn handle_new_session(
         &self,
         request: NewSessionRequest,
@@ -75,8 +676,20 @@ impl RhaiAgent {
     ) -> Result<(), sacp::Error> {
         tracing::debug!("New session request with cwd: {:?}", request.cwd);
 
+        if let Err(e) = check_mcp_servers_supported(&request.mcp_servers) {
+            return request_cx.respond_with_error(e);
+        }
+
         let session_id = SessionId::new(uuid::Uuid::new_v4().to_string());
-        self.create_session(&session_id, request.mcp_servers);
+        self.create_session(
+            &session_id,
+            StoredSession {
+                mcp_servers: request.mcp_servers,
+                ..Default::default()
+            },
+            request.cwd,
+        );
+        self.save_session(&session_id).await;
 
         request_cx.respond(NewSessionResponse::new(session_id))
     }
@@ -85,10 +698,52 @@ impl RhaiAgent {
         &self,
         request: LoadSessionRequest,
         request_cx: JrRequestCx<LoadSessionResponse>,
+        cx: JrConnectionCx<AgentToClient>,
     ) -> Result<(), sacp::Error> {
         tracing::debug!("Load session request: {:?}", request.session_id);
 
-        self.create_session(&request.session_id, vec![]);
+        let session_id = request.session_id;
+
+        if let Err(e) = check_mcp_servers_supported(&request.mcp_servers) {
+            return request_cx.respond_with_error(e);
+        }
+
+        // Prefer a session that is still in memory, otherwise rebuild it from the store
+        let transcript = match self.get_transcript(&session_id) {
+            Some(transcript) => {
+                if !request.mcp_servers.is_empty() {
+                    self.set_mcp_servers(&session_id, request.mcp_servers);
+                }
+                self.set_cwd(&session_id, request.cwd);
+                transcript
+            }
+            None => {
+                let stored = match &self.session_store {
+                    Some(store) => store
+                        .load(&session_id)
+                        .await
+                        .map_err(|e| sacp::Error::internal_error().data(e))?,
+                    None => None,
+                };
+                let Some(mut stored) = stored else {
+                    return request_cx.respond_with_error(sacp::Error::resource_not_found(Some(
+                        session_id.to_string(),
+                    )));
+                };
+                if !request.mcp_servers.is_empty() {
+                    stored.mcp_servers = request.mcp_servers;
+                }
+                let transcript = stored.transcript.clone();
+                self.create_session(&session_id, stored, request.cwd);
+                transcript
+            }
+        };
+
+        // Replay the conversation so the client can show it
+        for update in transcript {
+            cx.send_notification(SessionNotification::new(session_id.clone(), update))?;
+        }
+        self.save_session(&session_id).await;
 
         request_cx.respond(LoadSessionResponse::new())
     }
@@ -102,9 +757,21 @@ impl RhaiAgent {
     ) -> Result<(), sacp::Error> {
         let session_id = request.session_id.clone();
 
+        if let Err(e) = check_prompt_supported(&request.prompt) {
+            return request_cx.respond_with_error(e);
+        }
+
+        for block in &request.prompt {
+            self.record_update(
+                &session_id,
+                &SessionUpdate::UserMessageChunk(ContentChunk::new(block.clone())),
+            );
+        }
+
         // Extract the Rhai script from the prompt
         let input_text = extract_text_from_prompt(&request.prompt);
         let script = extract_rhai_script(&input_text);
+        let attachments = describe_attachments(&request.prompt);
 
         tracing::debug!(
             "Executing Rhai script in session {}: {}",
@@ -112,286 +779,948 @@ impl RhaiAgent {
             script
         );
 
-        // Get MCP servers for this session
+        // Get MCP servers, their connections and persistent script state for this session
         let mcp_servers = self.get_mcp_servers(&session_id).unwrap_or_default();
+        let mcp_clients = self.get_mcp_clients(&session_id).unwrap_or_default();
+        let script_state = self.get_script_state(&session_id).unwrap_or_default();
+        let cwd = self.get_cwd(&session_id).unwrap_or_default();
+        let cancel_token = self.start_prompt(&session_id);
 
         // Create channel for Rhai -> async communication
         let (msg_tx, mut msg_rx) = mpsc::unbounded_channel::<RhaiMessage>();
 
         // Spawn blocking task to run Rhai
         let script_clone = script.clone();
-        let rhai_handle =
-            tokio::task::spawn_blocking(move || run_rhai_script(&script_clone, msg_tx));
+        let rhai_cwd = cwd.clone();
+        let rhai_cancel_token = cancel_token.clone();
+        let rhai_handle = tokio::task::spawn_blocking(move || {
+            let mut state = script_state.lock().unwrap();
+            run_rhai_script(
+                &script_clone,
+                &mut state,
+                &rhai_cwd,
+                &attachments,
+                msg_tx,
+                rhai_cancel_token,
+            )
+        });
 
         // Process messages from Rhai execution
         while let Some(msg) = msg_rx.recv().await {
             match msg {
                 RhaiMessage::Say(text) => {
                     tracing::debug!(?session_id, ?text, "Rhai say()");
-                    cx.send_notification(SessionNotification::new(
-                        session_id.clone(),
+                    self.send_update(
+                        &cx,
+                        &session_id,
                         SessionUpdate::AgentMessageChunk(ContentChunk::new(text.into())),
-                    ))?;
+                    )?;
+                }
+                RhaiMessage::Think(text) => {
+                    tracing::debug!(?session_id, ?text, "Rhai think()");
+                    self.send_update(
+                        &cx,
+                        &session_id,
+                        SessionUpdate::AgentThoughtChunk(ContentChunk::new(text.into())),
+                    )?;
+                }
+                RhaiMessage::Ask {
+                    question,
+                    options,
+                    response_tx,
+                } => {
+                    let tool_call_id = ToolCallId::new(uuid::Uuid::new_v4().to_string());
+                    let tool_call = ToolCall::new(tool_call_id.clone(), question)
+                        .kind(ToolKind::Other)
+                        .status(ToolCallStatus::Pending);
+                    self.send_update(&cx, &session_id, SessionUpdate::ToolCall(tool_call.clone()))?;
+
+                    let response = cx
+                        .send_request(RequestPermissionRequest::new(
+                            session_id.clone(),
+                            tool_call.into(),
+                            options,
+                        ))
+                        .block_task()
+                        .await;
+                    let result = match response.map(|response| response.outcome) {
+                        Ok(RequestPermissionOutcome::Selected(selected)) => {
+                            Ok(selected.option_id.0.to_string())
+                        }
+                        Ok(_) => Err("The question was cancelled".to_string()),
+                        Err(e) => Err(format!("Failed to ask the user: {}", e)),
+                    };
+
+                    let fields = match &result {
+                        Ok(option_id) => ToolCallUpdateFields::new()
+                            .status(ToolCallStatus::Completed)
+                            .raw_output(serde_json::json!({ "selected": option_id })),
+                        Err(e) => ToolCallUpdateFields::new()
+                            .status(ToolCallStatus::Failed)
+                            .content(vec![ContentBlock::Text(TextContent::new(e)).into()]),
+                    };
+                    self.send_update(
+                        &cx,
+                        &session_id,
+                        SessionUpdate::ToolCallUpdate(ToolCallUpdate::new(tool_call_id, fields)),
+                    )?;
+                    let _ = response_tx.send(result);
+                }
+                RhaiMessage::SetPlan(entries) => {
+                    let plan = self.set_plan(&session_id, entries);
+                    self.send_update(&cx, &session_id, SessionUpdate::Plan(plan))?;
+                }
+                RhaiMessage::UpdatePlanEntry {
+                    index,
+                    status,
+                    response_tx,
+                } => {
+                    let result = match self.update_plan_entry(&session_id, index, status) {
+                        Ok(plan) => {
+                            self.send_update(&cx, &session_id, SessionUpdate::Plan(plan))?;
+                            Ok(())
+                        }
+                        Err(e) => Err(e),
+                    };
+                    let _ = response_tx.send(result);
                 }
                 RhaiMessage::ListTools {
                     server,
+                    timeout,
                     response_tx,
                 } => {
-                    let result = self.list_tools_async(&mcp_servers, &server).await;
+                    let deadline =
+                        Instant::now() + self.mcp_timeout(&mcp_servers, &server, timeout);
+                    let result = tokio::select! {
+                        result = list_tools_async(&mcp_clients, &mcp_servers, &server, deadline) => result,
+                        _ = cancel_token.cancelled() => Err(McpError::new(McpErrorKind::Cancelled, "Request cancelled")),
+                    };
                     let _ = response_tx.send(result);
                 }
                 RhaiMessage::CallTool {
                     server,
                     tool,
                     args,
+                    timeout,
                     response_tx,
                 } => {
-                    let result = self
-                        .call_tool_async(&mcp_servers, &server, &tool, &args)
-                        .await;
+                    // Show the call in the client's tool-call timeline
+                    let tool_call_id = ToolCallId::new(uuid::Uuid::new_v4().to_string());
+                    let tool_call =
+                        ToolCall::new(tool_call_id.clone(), format!("{} ({})", tool, server))
+                            .kind(ToolKind::Other)
+                            .status(ToolCallStatus::Pending)
+                            .raw_input(args.clone());
+                    self.send_update(&cx, &session_id, SessionUpdate::ToolCall(tool_call.clone()))?;
+
+                    // A server's own setting overrides the agent's policy
+                    let policy = mcp_pool::configured_permissions(&mcp_servers, &server)
+                        .unwrap_or(self.permission_policy);
+                    let needs_permission = match policy {
+                        PermissionPolicy::Never => Ok(false),
+                        PermissionPolicy::Always => Ok(true),
+                        PermissionPolicy::Annotations => {
+                            let deadline =
+                                Instant::now() + self.mcp_timeout(&mcp_servers, &server, timeout);
+                            tokio::select! {
+                                result = tool_annotations(&mcp_clients, &mcp_servers, &server, &tool, deadline) => {
+                                    result.map(|annotations| !permissions::is_read_only(annotations.as_ref()))
+                                }
+                                _ = cancel_token.cancelled() => Err(McpError::new(McpErrorKind::Cancelled, "Request cancelled")),
+                            }
+                        }
+                    };
+                    let allowed = match needs_permission {
+                        Ok(true) => {
+                            let action = format!("mcp:{}:{}", server, tool);
+                            if self
+                                .permission_granted(&cx, &session_id, &action, tool_call)
+                                .await
+                            {
+                                Ok(())
+                            } else {
+                                Err(McpError::new(
+                                    McpErrorKind::PermissionDenied,
+                                    format!("Permission to call {} was denied", tool),
+                                ))
+                            }
+                        }
+                        Ok(false) => Ok(()),
+                        Err(e) => Err(e),
+                    };
+
+                    let result = match allowed {
+                        Ok(()) => {
+                            self.send_update(
+                                &cx,
+                                &session_id,
+                                SessionUpdate::ToolCallUpdate(ToolCallUpdate::new(
+                                    tool_call_id.clone(),
+                                    ToolCallUpdateFields::new().status(ToolCallStatus::InProgress),
+                                )),
+                            )?;
+
+                            // Time spent waiting for the user does not count
+                            // against the call's timeout
+                            let deadline =
+                                Instant::now() + self.mcp_timeout(&mcp_servers, &server, timeout);
+                            tokio::select! {
+                                result = call_tool_async(&mcp_clients, &mcp_servers, &server, &tool, &args, deadline) => result,
+                                _ = cancel_token.cancelled() => Err(McpError::new(McpErrorKind::Cancelled, "Request cancelled")),
+                            }
+                        }
+                        Err(e) => Err(e),
+                    };
+
+                    self.send_update(
+                        &cx,
+                        &session_id,
+                        SessionUpdate::ToolCallUpdate(ToolCallUpdate::new(
+                            tool_call_id,
+                            tool_call_outcome(&result),
+                        )),
+                    )?;
+                    let _ = response_tx.send(result.map(|result| describe_tool_result(&result)));
+                }
+                RhaiMessage::WriteFile {
+                    path,
+                    content,
+                    response_tx,
+                } => {
+                    let path = cwd.join(path);
+                    let allowed = self.fs_policy.check(&cwd, &path);
+                    let tool_call_id = ToolCallId::new(uuid::Uuid::new_v4().to_string());
+                    let mut tool_call =
+                        ToolCall::new(tool_call_id.clone(), format!("Write {}", path.display()))
+                            .kind(ToolKind::Edit)
+                            .status(ToolCallStatus::Pending)
+                            .locations(vec![ToolCallLocation::new(&path)]);
+                    if allowed.is_ok()
+                        && let Some(old_text) = self.diff_base(&cx, &session_id, &path).await
+                    {
+                        tool_call = tool_call
+                            .content(vec![Diff::new(&path, &content).old_text(old_text).into()]);
+                    }
+                    self.send_update(&cx, &session_id, SessionUpdate::ToolCall(tool_call.clone()))?;
+
+                    let result = match allowed {
+                        Ok(()) => {
+                            if self.permission_policy == PermissionPolicy::Never
+                                || self
+                                    .permission_granted(&cx, &session_id, "write_file", tool_call)
+                                    .await
+                            {
+                                self.send_update(
+                                    &cx,
+                                    &session_id,
+                                    SessionUpdate::ToolCallUpdate(ToolCallUpdate::new(
+                                        tool_call_id.clone(),
+                                        ToolCallUpdateFields::new()
+                                            .status(ToolCallStatus::InProgress),
+                                    )),
+                                )?;
+                                self.write_text_file(&cx, &session_id, &path, content).await
+                            } else {
+                                Err(format!("Permission to write {} was denied", path.display()))
+                            }
+                        }
+                        Err(e) => Err(e),
+                    };
+                    let fields = match &result {
+                        Ok(()) => ToolCallUpdateFields::new().status(ToolCallStatus::Completed),
+                        Err(e) => ToolCallUpdateFields::new()
+                            .status(ToolCallStatus::Failed)
+                            .content(vec![ContentBlock::Text(TextContent::new(e)).into()]),
+                    };
+                    self.send_update(
+                        &cx,
+                        &session_id,
+                        SessionUpdate::ToolCallUpdate(ToolCallUpdate::new(tool_call_id, fields)),
+                    )?;
                     let _ = response_tx.send(result);
                 }
-                RhaiMessage::WriteFile { path, content } => {
-                    // Attempt to write the file asynchronously
-                    let write_result = tokio::fs::write(&path, content).await;
-                    match write_result {
-                        Ok(_) => {
-                            let update = ToolCallUpdate::new(
-                                "write_file_id",
-                                ToolCallUpdateFields::new()
-                                    .status(ToolCallStatus::Completed)
-                                    .locations(vec![ToolCallLocation::new(path)])
-                                    .content(vec![
-                                        ContentBlock::Text(TextContent::new(
-                                            "Finished writing file.",
-                                        ))
-                                        .into(),
-                                    ]),
-                            );
-                            cx.send_notification(SessionNotification::new(
-                                session_id.clone(),
-                                SessionUpdate::ToolCallUpdate(update),
-                            ))?;
+                RhaiMessage::ReadFile {
+                    path,
+                    line,
+                    limit,
+                    response_tx,
+                } => {
+                    let path = cwd.join(path);
+                    let tool_call_id = ToolCallId::new(uuid::Uuid::new_v4().to_string());
+                    self.send_update(
+                        &cx,
+                        &session_id,
+                        SessionUpdate::ToolCall(
+                            ToolCall::new(tool_call_id.clone(), format!("Read {}", path.display()))
+                                .kind(ToolKind::Read)
+                                .status(ToolCallStatus::InProgress)
+                                .locations(vec![ToolCallLocation::new(&path).line(line)]),
+                        ),
+                    )?;
+
+                    let result = match self.fs_policy.check(&cwd, &path) {
+                        Ok(()) => {
+                            self.read_text_file(&cx, &session_id, &path, line, limit)
+                                .await
+                        }
+                        Err(e) => Err(e),
+                    };
+                    let fields = match &result {
+                        Ok(_) => ToolCallUpdateFields::new().status(ToolCallStatus::Completed),
+                        Err(e) => ToolCallUpdateFields::new()
+                            .status(ToolCallStatus::Failed)
+                            .content(vec![ContentBlock::Text(TextContent::new(e)).into()]),
+                    };
+                    self.send_update(
+                        &cx,
+                        &session_id,
+                        SessionUpdate::ToolCallUpdate(ToolCallUpdate::new(tool_call_id, fields)),
+                    )?;
+                    let _ = response_tx.send(result);
+                }
+                RhaiMessage::RunInTerminal {
+                    command,
+                    args,
+                    cwd: command_cwd,
+                    env,
+                    output_byte_limit,
+                    response_tx,
+                } => {
+                    // ACP requires an absolute working directory
+                    let command_cwd =
+                        std::path::absolute(cwd.join(command_cwd.unwrap_or_default()))
+                            .unwrap_or_else(|_| cwd.clone());
+                    let tool_call_id = ToolCallId::new(uuid::Uuid::new_v4().to_string());
+                    let tool_call =
+                        ToolCall::new(tool_call_id.clone(), command_line(&command, &args))
+                            .kind(ToolKind::Execute)
+                            .status(ToolCallStatus::Pending)
+                            .raw_input(serde_json::json!({
+                                "command": command,
+                                "args": args,
+                                "cwd": command_cwd,
+                            }));
+                    self.send_update(&cx, &session_id, SessionUpdate::ToolCall(tool_call.clone()))?;
+
+                    let result = if !self.client_has_terminals() {
+                        Err("The client does not support terminals".to_string())
+                    } else if self.permission_policy != PermissionPolicy::Never
+                        && !self
+                            .permission_granted(
+                                &cx,
+                                &session_id,
+                                &format!("terminal:{}", command),
+                                tool_call,
+                            )
+                            .await
+                    {
+                        Err(format!("Permission to run {} was denied", command))
+                    } else {
+                        let request = CreateTerminalRequest::new(session_id.clone(), &command)
+                            .args(args)
+                            .env(
+                                env.into_iter()
+                                    .map(|(name, value)| EnvVariable::new(name, value))
+                                    .collect(),
+                            )
+                            .cwd(command_cwd)
+                            .output_byte_limit(output_byte_limit);
+                        self.run_in_terminal(
+                            &cx,
+                            &session_id,
+                            &tool_call_id,
+                            request,
+                            &cancel_token,
+                        )
+                        .await
+                    };
+
+                    // Keep the embedded terminal as the tool call's content
+                    // unless the command could not be run at all
+                    let fields = match &result {
+                        Ok(output) => {
+                            let succeeded = output
+                                .exit_status
+                                .as_ref()
+                                .is_some_and(|status| status.exit_code == Some(0));
+                            ToolCallUpdateFields::new().status(if succeeded {
+                                ToolCallStatus::Completed
+                            } else {
+                                ToolCallStatus::Failed
+                            })
+                        }
+                        Err(e) => ToolCallUpdateFields::new()
+                            .status(ToolCallStatus::Failed)
+                            .content(vec![ContentBlock::Text(TextContent::new(e)).into()]),
+                    };
+                    self.send_update(
+                        &cx,
+                        &session_id,
+                        SessionUpdate::ToolCallUpdate(ToolCallUpdate::new(tool_call_id, fields)),
+                    )?;
+                    let _ =
+                        response_tx.send(result.map(|output| describe_terminal_output(&output)));
+                }
+                RhaiMessage::Exec {
+                    command,
+                    args,
+                    cwd: process_cwd,
+                    env,
+                    stdin,
+                    timeout,
+                    response_tx,
+                } => {
+                    let process_cwd = cwd.join(process_cwd.unwrap_or_default());
+                    let tool_call_id = ToolCallId::new(uuid::Uuid::new_v4().to_string());
+                    let tool_call =
+                        ToolCall::new(tool_call_id.clone(), command_line(&command, &args))
+                            .kind(ToolKind::Execute)
+                            .status(ToolCallStatus::Pending)
+                            .raw_input(serde_json::json!({
+                                "command": command,
+                                "args": args,
+                                "cwd": process_cwd,
+                            }));
+                    self.send_update(&cx, &session_id, SessionUpdate::ToolCall(tool_call.clone()))?;
+
+                    // Find the program before applying the script's environment,
+                    // which could otherwise point `command` at another program
+                    let program = if !self.allowed_commands.contains(&command) {
+                        Err(format!("{} is not an allowed command", command))
+                    } else {
+                        check_process_env(&env).and_then(|()| resolve_command(&command))
+                    };
+
+                    let result = match program {
+                        Err(e) => Err(e),
+                        Ok(program) => {
+                            if self.permission_policy != PermissionPolicy::Never
+                                && !self
+                                    .permission_granted(
+                                        &cx,
+                                        &session_id,
+                                        &format!("process:{}", command),
+                                        tool_call,
+                                    )
+                                    .await
+                            {
+                                Err(format!("Permission to run {} was denied", command))
+                            } else {
+                                self.send_update(
+                                    &cx,
+                                    &session_id,
+                                    SessionUpdate::ToolCallUpdate(ToolCallUpdate::new(
+                                        tool_call_id.clone(),
+                                        ToolCallUpdateFields::new()
+                                            .status(ToolCallStatus::InProgress),
+                                    )),
+                                )?;
+                                let mut process = tokio::process::Command::new(program);
+                                process.args(&args).current_dir(&process_cwd).envs(env);
+                                run_process(process, stdin, timeout, &cancel_token)
+                                    .await
+                                    .map_err(|e| format!("Failed to run {}: {}", command, e))
+                            }
                         }
-                        Err(e) => {
-                            let update = ToolCallUpdate::new(
-                                "write_file_id",
-                                ToolCallUpdateFields::new()
-                                    .status(ToolCallStatus::Failed)
-                                    .locations(vec![ToolCallLocation::new(path)])
-                                    .content(vec![
-                                        ContentBlock::Text(TextContent::new(format!("{:?}", e)))
-                                            .into(),
-                                    ]),
-                            );
-                            cx.send_notification(SessionNotification::new(
-                                session_id.clone(),
-                                SessionUpdate::ToolCallUpdate(update),
-                            ))?;
+                    };
+
+                    let fields = match &result {
+                        Ok(output) => {
+                            let text = [&output.stdout, &output.stderr]
+                                .map(|bytes| String::from_utf8_lossy(bytes))
+                                .concat();
+                            let fields = ToolCallUpdateFields::new()
+                                .status(if output.status.success() {
+                                    ToolCallStatus::Completed
+                                } else {
+                                    ToolCallStatus::Failed
+                                })
+                                .raw_output(describe_process_output(output));
+                            if text.is_empty() {
+                                fields
+                            } else {
+                                fields.content(vec![
+                                    ContentBlock::Text(TextContent::new(text)).into(),
+                                ])
+                            }
                         }
-                    }
+                        Err(e) => ToolCallUpdateFields::new()
+                            .status(ToolCallStatus::Failed)
+                            .content(vec![ContentBlock::Text(TextContent::new(e)).into()]),
+                    };
+                    self.send_update(
+                        &cx,
+                        &session_id,
+                        SessionUpdate::ToolCallUpdate(ToolCallUpdate::new(tool_call_id, fields)),
+                    )?;
+                    let _ = response_tx.send(result.map(|output| describe_process_output(&output)));
                 }
             }
         }
 
         // Wait for Rhai to complete and handle any errors
-        match rhai_handle.await {
-            Ok(Ok(())) => {
-                tracing::debug!(?session_id, "Rhai script completed successfully");
+        let rhai_result = rhai_handle.await;
+        let stop_reason = if cancel_token.is_cancelled() {
+            tracing::debug!(?session_id, "Rhai script cancelled");
+            StopReason::Cancelled
+        } else {
+            match rhai_result {
+                Ok(Ok(())) => {
+                    tracing::debug!(?session_id, "Rhai script completed successfully");
+                }
+                Ok(Err(e)) => {
+                    // Rhai execution error - send error info to client
+                    let error_msg = format!("Rhai error: {}", e);
+                    tracing::warn!(?session_id, ?error_msg, "Rhai script failed");
+                    self.send_update(
+                        &cx,
+                        &session_id,
+                        SessionUpdate::AgentMessageChunk(ContentChunk::new(error_msg.into())),
+                    )?;
+                }
+                Err(e) => {
+                    // Task panicked
+                    let error_msg = format!("Rhai task panicked: {}", e);
+                    tracing::error!(?session_id, ?error_msg, "Rhai task panic");
+                    self.send_update(
+                        &cx,
+                        &session_id,
+                        SessionUpdate::AgentMessageChunk(ContentChunk::new(error_msg.into())),
+                    )?;
+                }
             }
-            Ok(Err(e)) => {
-                // Rhai execution error - send error info to client
-                let error_msg = format!("Rhai error: {}", e);
-                tracing::warn!(?session_id, ?error_msg, "Rhai script failed");
-                cx.send_notification(SessionNotification::new(
-                    session_id.clone(),
-                    SessionUpdate::AgentMessageChunk(ContentChunk::new(error_msg.into())),
-                ))?;
+            StopReason::EndTurn
+        };
+
+        // Close connections that `session/load` replaced during the prompt
+        drop(mcp_clients);
+        self.replace_stale_mcp_clients(&session_id);
+
+        self.save_session(&session_id).await;
+
+        request_cx.respond(PromptResponse::new(stop_reason))
+    }
+}
+
+/// List the tools of the MCP server named `server_name`
+async fn list_tools_async(
+    mcp_clients: &McpClientPool,
+    mcp_servers: &[McpServer],
+    server_name: &str,
+    deadline: Instant,
+) -> Result<Vec<serde_json::Value>, McpError> {
+    let tools = fetch_tools(mcp_clients, mcp_servers, server_name, deadline).await?;
+    Ok(tools.into_iter().map(describe_tool).collect())
+}
+
+/// The annotations of `tool_name` on the MCP server named `server_name`, if it
+/// has any. The server's tools are listed once and cached until it says its
+/// tool list changed.
+async fn tool_annotations(
+    mcp_clients: &McpClientPool,
+    mcp_servers: &[McpServer],
+    server_name: &str,
+    tool_name: &str,
+    deadline: Instant,
+) -> Result<Option<rmcp::model::ToolAnnotations>, McpError> {
+    let (mcp_client, cache) = tokio::time::timeout_at(
+        deadline,
+        mcp_clients.get_with_tool_annotations(mcp_servers, server_name),
+    )
+    .await
+    .unwrap_or_else(|_| Err(connect_timeout_error(server_name)))?;
+
+    let generation = {
+        let cache = cache.lock().unwrap();
+        if let Some(annotations) = cache.get(tool_name) {
+            return Ok(annotations);
+        }
+        cache.generation()
+    };
+    let annotations: HashMap<_, _> = list_all_tools(&mcp_client, deadline)
+        .await?
+        .into_iter()
+        .map(|tool| (tool.name.to_string(), tool.annotations))
+        .collect();
+    let tool_annotations = annotations.get(tool_name).cloned().flatten();
+    cache.lock().unwrap().store(generation, annotations);
+    Ok(tool_annotations)
+}
+
+/// Fetch the tools of the MCP server named `server_name`
+async fn fetch_tools(
+    mcp_clients: &McpClientPool,
+    mcp_servers: &[McpServer],
+    server_name: &str,
+    deadline: Instant,
+) -> Result<Vec<rmcp::model::Tool>, McpError> {
+    let mcp_client = connect_before(mcp_clients, mcp_servers, server_name, deadline).await?;
+    list_all_tools(&mcp_client, deadline).await
+}
+
+/// List every tool of an MCP server, following `next_cursor` through each page
+async fn list_all_tools(
+    mcp_client: &rmcp::Peer<rmcp::RoleClient>,
+    deadline: Instant,
+) -> Result<Vec<rmcp::model::Tool>, McpError> {
+    use rmcp::model::{ClientRequest, ListToolsRequest, PaginatedRequestParam, ServerResult};
+
+    let mut tools = Vec::new();
+    let mut cursor = None;
+    loop {
+        let request =
+            ClientRequest::ListToolsRequest(ListToolsRequest::with_param(PaginatedRequestParam {
+                cursor,
+            }));
+        match send_before(mcp_client, request, deadline).await {
+            Ok(ServerResult::ListToolsResult(page)) => {
+                tools.extend(page.tools);
+                cursor = page.next_cursor;
+                if cursor.is_none() {
+                    return Ok(tools);
+                }
             }
-            Err(e) => {
-                // Task panicked
-                let error_msg = format!("Rhai task panicked: {}", e);
-                tracing::error!(?session_id, ?error_msg, "Rhai task panic");
-                cx.send_notification(SessionNotification::new(
-                    session_id.clone(),
-                    SessionUpdate::AgentMessageChunk(ContentChunk::new(error_msg.into())),
-                ))?;
+            Ok(_) => {
+                return Err(McpError::from_service(
+                    "Failed to list tools",
+                    rmcp::ServiceError::UnexpectedResponse,
+                ));
             }
+            Err(e) => return Err(McpError::from_service("Failed to list tools", e)),
         }
+    }
+}
+
+/// Call `tool_name` on the MCP server named `server_name`
+async fn call_tool_async(
+    mcp_clients: &McpClientPool,
+    mcp_servers: &[McpServer],
+    server_name: &str,
+    tool_name: &str,
+    args: &serde_json::Value,
+    deadline: Instant,
+) -> Result<rmcp::model::CallToolResult, McpError> {
+    use rmcp::model::{CallToolRequest, CallToolRequestParam, ClientRequest, ServerResult};
 
-        request_cx.respond(PromptResponse::new(StopReason::EndTurn))
+    let mcp_client = connect_before(mcp_clients, mcp_servers, server_name, deadline).await?;
+
+    let request = ClientRequest::CallToolRequest(CallToolRequest::new(CallToolRequestParam {
+        name: tool_name.to_string().into(),
+        arguments: args.as_object().cloned(),
+    }));
+    match send_before(&mcp_client, request, deadline).await {
+        Ok(ServerResult::CallToolResult(tool_result)) => Ok(tool_result),
+        Ok(_) => Err(McpError::from_service(
+            "Failed to call tool",
+            rmcp::ServiceError::UnexpectedResponse,
+        )),
+        Err(e) => Err(McpError::from_service("Failed to call tool", e)),
     }
+}
 
-    async fn list_tools_async(
-        &self,
-        mcp_servers: &[McpServer],
-        server_name: &str,
-    ) -> Result<Vec<String>, String> {
-        use rmcp::ServiceExt;
+/// `command` and `args` joined into one line, for tool-call titles
+fn command_line(command: &str, args: &[String]) -> String {
+    std::iter::once(command)
+        .chain(args.iter().map(String::as_str))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
 
-        let mcp_server = mcp_servers
+/// Reject `PATH` and the dynamic loader's variables, which change which
+/// program a process runs or what it loads. Other variables are passed on, so
+/// an allowed command that runs programs named in its environment (such as
+/// `RUSTC_WRAPPER` for `cargo`) can still run them.
+fn check_process_env(env: &[(String, String)]) -> Result<(), String> {
+    for (name, _) in env {
+        if name == "PATH" || name.starts_with("LD_") || name.starts_with("DYLD_") {
+            return Err(format!("process::exec cannot set {}", name));
+        }
+    }
+    Ok(())
+}
+
+/// The absolute path of the program `command` names, looked up on the agent's
+/// own `PATH` unless it has a directory in it. Relative paths are resolved
+/// against the agent's working directory rather than the process's `cwd`,
+/// which the script chooses. Symlinks are kept, so multi-call programs still
+/// see the name they were run as.
+fn resolve_command(command: &str) -> Result<PathBuf, String> {
+    let program = if Path::new(command).components().count() > 1 {
+        PathBuf::from(command)
+    } else {
+        std::env::var_os("PATH")
             .iter()
-            .find(|s| match s {
-                McpServer::Stdio(stdio) => stdio.name == server_name,
-                McpServer::Http(http) => http.name == server_name,
-                McpServer::Sse(sse) => sse.name == server_name,
-                _ => false,
-            })
-            .ok_or_else(|| format!("MCP server '{}' not found", server_name))?;
+            .flat_map(std::env::split_paths)
+            .map(|dir| dir.join(format!("{}{}", command, std::env::consts::EXE_SUFFIX)))
+            .find(|path| is_executable(path))
+            .ok_or_else(|| format!("Failed to run {}: not found on the PATH", command))?
+    };
+    std::path::absolute(program).map_err(|e| format!("Failed to run {}: {}", command, e))
+}
 
-        match mcp_server {
-            McpServer::Stdio(stdio) => {
-                use rmcp::transport::{ConfigureCommandExt, TokioChildProcess};
-                use tokio::process::Command;
-
-                let transport =
-                    TokioChildProcess::new(Command::new(&stdio.command).configure(|cmd| {
-                        cmd.args(&stdio.args);
-                        for env_var in &stdio.env {
-                            cmd.env(&env_var.name, &env_var.value);
-                        }
-                    }))
-                    .map_err(|e| format!("Failed to spawn MCP server: {}", e))?;
-
-                let mcp_client = ()
-                    .serve(transport)
-                    .await
-                    .map_err(|e| format!("Failed to connect to MCP server: {}", e))?;
-
-                let tools_result = mcp_client
-                    .list_tools(None)
-                    .await
-                    .map_err(|e| format!("Failed to list tools: {}", e))?;
-
-                let _ = mcp_client.cancel().await;
-
-                Ok(tools_result
-                    .tools
-                    .into_iter()
-                    .map(|t| t.name.to_string())
-                    .collect())
-            }
-            McpServer::Http(http) => {
-                use rmcp::transport::StreamableHttpClientTransport;
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
 
-                let transport = StreamableHttpClientTransport::from_uri(http.url.clone());
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
 
-                let mcp_client = ()
-                    .serve(transport)
-                    .await
-                    .map_err(|e| format!("Failed to connect to HTTP MCP server: {}", e))?;
+/// Run `command` on the agent's machine, writing `stdin` to it and capturing
+/// its output. The process is killed if it outlives `timeout` or the prompt is
+/// cancelled.
+async fn run_process(
+    mut command: tokio::process::Command,
+    stdin: Option<String>,
+    timeout: Option<Duration>,
+    cancel_token: &CancellationToken,
+) -> Result<std::process::Output, String> {
+    use std::process::Stdio;
+    use tokio::io::AsyncWriteExt;
 
-                let tools_result = mcp_client
-                    .list_tools(None)
-                    .await
-                    .map_err(|e| format!("Failed to list tools: {}", e))?;
+    command
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    let mut child = command.spawn().map_err(|e| e.to_string())?;
 
-                let _ = mcp_client.cancel().await;
+    // Write stdin while the output is read, so that neither side blocks on a
+    // full pipe; dropping the pipe afterwards closes it
+    if let (Some(input), Some(mut pipe)) = (stdin, child.stdin.take()) {
+        tokio::spawn(async move {
+            let _ = pipe.write_all(input.as_bytes()).await;
+        });
+    }
 
-                Ok(tools_result
-                    .tools
-                    .into_iter()
-                    .map(|t| t.name.to_string())
-                    .collect())
-            }
-            _ => Err("SSE MCP servers are not currently supported".to_string()),
+    let timed_out = async {
+        match timeout {
+            Some(timeout) => tokio::time::sleep(timeout).await,
+            None => std::future::pending().await,
         }
+    };
+    tokio::select! {
+        output = child.wait_with_output() => output.map_err(|e| e.to_string()),
+        _ = timed_out => Err(format!("timed out after {} ms", timeout.unwrap_or_default().as_millis())),
+        _ = cancel_token.cancelled() => Err("cancelled".to_string()),
     }
+}
 
-    async fn call_tool_async(
-        &self,
-        mcp_servers: &[McpServer],
-        server_name: &str,
-        tool_name: &str,
-        args: &serde_json::Value,
-    ) -> Result<serde_json::Value, String> {
-        use rmcp::{ServiceExt, model::CallToolRequestParam};
-
-        let mcp_server = mcp_servers
-            .iter()
-            .find(|s| match s {
-                McpServer::Stdio(stdio) => stdio.name == server_name,
-                McpServer::Http(http) => http.name == server_name,
-                McpServer::Sse(sse) => sse.name == server_name,
-                _ => false,
-            })
-            .ok_or_else(|| format!("MCP server '{}' not found", server_name))?;
+/// Describe a finished local process as a JSON object for scripts
+fn describe_process_output(output: &std::process::Output) -> serde_json::Value {
+    serde_json::json!({
+        "exit_code": output.status.code(),
+        "stdout": String::from_utf8_lossy(&output.stdout),
+        "stderr": String::from_utf8_lossy(&output.stderr),
+    })
+}
 
-        match mcp_server {
-            McpServer::Stdio(stdio) => {
-                use rmcp::transport::{ConfigureCommandExt, TokioChildProcess};
-                use tokio::process::Command;
-
-                let transport =
-                    TokioChildProcess::new(Command::new(&stdio.command).configure(|cmd| {
-                        cmd.args(&stdio.args);
-                        for env_var in &stdio.env {
-                            cmd.env(&env_var.name, &env_var.value);
-                        }
-                    }))
-                    .map_err(|e| format!("Failed to spawn MCP server: {}", e))?;
+/// Describe a finished terminal command as a JSON object for scripts
+fn describe_terminal_output(output: &TerminalOutputResponse) -> serde_json::Value {
+    let exit_status = output.exit_status.as_ref();
+    serde_json::json!({
+        "exit_code": exit_status.and_then(|status| status.exit_code),
+        "signal": exit_status.and_then(|status| status.signal.clone()),
+        "output": output.output,
+        "truncated": output.truncated,
+    })
+}
 
-                let mcp_client = ()
-                    .serve(transport)
-                    .await
-                    .map_err(|e| format!("Failed to connect to MCP server: {}", e))?;
+/// The final status, content and raw output of an MCP tool call, for the
+/// client's tool-call timeline
+fn tool_call_outcome(
+    result: &Result<rmcp::model::CallToolResult, McpError>,
+) -> ToolCallUpdateFields {
+    match result {
+        Ok(tool_result) => {
+            let status = if tool_result.is_error == Some(true) {
+                ToolCallStatus::Failed
+            } else {
+                ToolCallStatus::Completed
+            };
+            ToolCallUpdateFields::new()
+                .status(status)
+                .content(
+                    tool_result
+                        .content
+                        .iter()
+                        .map(|content| acp_content(content).into())
+                        .collect::<Vec<_>>(),
+                )
+                .raw_output(describe_tool_result(tool_result))
+        }
+        Err(e) => ToolCallUpdateFields::new()
+            .status(ToolCallStatus::Failed)
+            .content(vec![
+                ContentBlock::Text(TextContent::new(e.message.clone())).into(),
+            ])
+            .raw_output(serde_json::json!({
+                "kind": e.kind.as_str(),
+                "message": e.message,
+            })),
+    }
+}
 
-                let tool_result = mcp_client
-                    .call_tool(CallToolRequestParam {
-                        name: tool_name.to_string().into(),
-                        arguments: args.as_object().cloned(),
-                    })
-                    .await
-                    .map_err(|e| format!("Failed to call tool: {}", e))?;
+/// Convert an MCP content block to ACP. ACP content blocks are modeled on
+/// MCP's, so they share a JSON representation.
+fn acp_content(content: &rmcp::model::Content) -> ContentBlock {
+    serde_json::to_value(content)
+        .and_then(serde_json::from_value)
+        .unwrap_or_else(|e| ContentBlock::Text(TextContent::new(format!("{:?}", e))))
+}
 
-                let _ = mcp_client.cancel().await;
+/// Get a client for `server_name`, giving up on connecting once `deadline` passes
+async fn connect_before(
+    mcp_clients: &McpClientPool,
+    mcp_servers: &[McpServer],
+    server_name: &str,
+    deadline: Instant,
+) -> Result<rmcp::Peer<rmcp::RoleClient>, McpError> {
+    tokio::time::timeout_at(deadline, mcp_clients.get(mcp_servers, server_name))
+        .await
+        .unwrap_or_else(|_| Err(connect_timeout_error(server_name)))
+}
 
-                extract_tool_result(tool_result)
-            }
-            McpServer::Http(http) => {
-                use rmcp::transport::StreamableHttpClientTransport;
+/// The error for an MCP server that did not connect before the deadline
+fn connect_timeout_error(server_name: &str) -> McpError {
+    McpError::new(
+        McpErrorKind::Timeout,
+        format!("Timed out connecting to MCP server '{}'", server_name),
+    )
+}
 
-                let transport = StreamableHttpClientTransport::from_uri(http.url.clone());
+/// Send `request` to an MCP server, giving up once `deadline` passes.
+/// rmcp notifies the server when a request times out so it can stop working on it.
+async fn send_before(
+    mcp_client: &rmcp::Peer<rmcp::RoleClient>,
+    request: rmcp::model::ClientRequest,
+    deadline: Instant,
+) -> Result<rmcp::model::ServerResult, rmcp::ServiceError> {
+    let options = rmcp::service::PeerRequestOptions {
+        timeout: Some(deadline.saturating_duration_since(Instant::now())),
+        meta: None,
+    };
+    mcp_client
+        .send_request_with_option(request, options)
+        .await?
+        .await_response()
+        .await
+}
 
-                let mcp_client = ()
-                    .serve(transport)
-                    .await
-                    .map_err(|e| format!("Failed to connect to HTTP MCP server: {}", e))?;
+/// Describe a tool for scripts, with snake_case keys to match Rhai naming
+fn describe_tool(tool: rmcp::model::Tool) -> serde_json::Value {
+    let annotations = tool.annotations.map(|annotations| {
+        serde_json::json!({
+            "title": annotations.title,
+            "read_only_hint": annotations.read_only_hint,
+            "destructive_hint": annotations.destructive_hint,
+            "idempotent_hint": annotations.idempotent_hint,
+            "open_world_hint": annotations.open_world_hint,
+        })
+    });
+    serde_json::json!({
+        "name": tool.name,
+        "title": tool.title,
+        "description": tool.description,
+        "input_schema": tool.input_schema,
+        "output_schema": tool.output_schema,
+        "annotations": annotations,
+    })
+}
 
-                let tool_result = mcp_client
-                    .call_tool(CallToolRequestParam {
-                        name: tool_name.to_string().into(),
-                        arguments: args.as_object().cloned(),
-                    })
-                    .await
-                    .map_err(|e| format!("Failed to call tool: {}", e))?;
+/// Describe a tool result for scripts: every content block, the structured content,
+/// the error flag, and (when there is one) the simple `value` from [`extract_tool_result`]
+fn describe_tool_result(result: &rmcp::model::CallToolResult) -> serde_json::Value {
+    let mut description = serde_json::json!({
+        "content": result.content.iter().map(describe_content).collect::<Vec<_>>(),
+        "structured_content": result.structured_content,
+        "is_error": result.is_error.unwrap_or(false),
+    });
+    if let Ok(value) = extract_tool_result(result) {
+        description["value"] = value;
+    }
+    description
+}
 
-                let _ = mcp_client.cancel().await;
+/// Describe a content block returned by a tool, with snake_case keys
+fn describe_content(content: &rmcp::model::Content) -> serde_json::Value {
+    use rmcp::model::{RawContent, ResourceContents};
 
-                extract_tool_result(tool_result)
-            }
-            _ => Err("SSE MCP servers are not currently supported".to_string()),
-        }
+    match &content.raw {
+        RawContent::Text(text) => serde_json::json!({
+            "type": "text",
+            "text": text.text,
+        }),
+        RawContent::Image(image) => serde_json::json!({
+            "type": "image",
+            "data": image.data,
+            "mime_type": image.mime_type,
+        }),
+        RawContent::Audio(audio) => serde_json::json!({
+            "type": "audio",
+            "data": audio.data,
+            "mime_type": audio.mime_type,
+        }),
+        RawContent::Resource(embedded) => match &embedded.resource {
+            ResourceContents::TextResourceContents {
+                uri,
+                mime_type,
+                text,
+                ..
+            } => serde_json::json!({
+                "type": "resource",
+                "uri": uri,
+                "mime_type": mime_type,
+                "text": text,
+            }),
+            ResourceContents::BlobResourceContents {
+                uri,
+                mime_type,
+                blob,
+                ..
+            } => serde_json::json!({
+                "type": "resource",
+                "uri": uri,
+                "mime_type": mime_type,
+                "blob": blob,
+            }),
+        },
+        RawContent::ResourceLink(link) => serde_json::json!({
+            "type": "resource_link",
+            "uri": link.uri,
+            "name": link.name,
+            "title": link.title,
+            "description": link.description,
+            "mime_type": link.mime_type,
+        }),
     }
 }
 
 /// Extract the result value from a CallToolResult.
 /// Prefers structured_content if available, otherwise tries to parse
 /// the first text content item as JSON, falling back to returning it as a string.
-fn extract_tool_result(result: rmcp::model::CallToolResult) -> Result<serde_json::Value, String> {
+fn extract_tool_result(result: &rmcp::model::CallToolResult) -> Result<serde_json::Value, String> {
     // Prefer structured_content if available
-    if let Some(structured) = result.structured_content {
-        return Ok(structured);
+    if let Some(structured) = &result.structured_content {
+        return Ok(structured.clone());
     }
 
     // Fall back to first text content
-    if let Some(content) = result.content.first() {
-        if let Some(text_content) = content.as_text() {
-            // Try parsing as JSON to preserve types (numbers, booleans, objects, etc.)
-            // If that fails, return as a plain string
-            return Ok(serde_json::from_str(&text_content.text)
-                .unwrap_or_else(|_| serde_json::Value::String(text_content.text.clone())));
-        }
+    if let Some(content) = result.content.first()
+        && let Some(text_content) = content.as_text()
+    {
+        // Try parsing as JSON to preserve types (numbers, booleans, objects, etc.)
+        // If that fails, return as a plain string
+        return Ok(serde_json::from_str(&text_content.text)
+            .unwrap_or_else(|_| serde_json::Value::String(text_content.text.clone())));
     }
 
     // No usable content
@@ -404,58 +1733,364 @@ impl Default for RhaiAgent {
     }
 }
 
-/// Run a Rhai script with the given message channel
-fn run_rhai_script(script: &str, msg_tx: mpsc::UnboundedSender<RhaiMessage>) -> Result<(), String> {
+/// Run a Rhai script with the given message channel.
+///
+/// Variables and functions defined by the script are kept in `state`
+/// so that later prompts in the same session can use them.
+fn run_rhai_script(
+    script: &str,
+    state: &mut ScriptState,
+    cwd: &Path,
+    attachments: &serde_json::Value,
+    msg_tx: mpsc::UnboundedSender<RhaiMessage>,
+    cancel_token: CancellationToken,
+) -> Result<(), String> {
     let mut engine = Engine::new();
 
+    // Abort the script once the prompt is cancelled
+    engine.on_progress(move |_| cancel_token.is_cancelled().then_some(Dynamic::UNIT));
+
+    // Expose the session's working directory and the prompt's images, audio
+    // and binary resources as the read-only `CWD` and `ATTACHMENTS`
+    // variables. Rhai flags `on_var` as deprecated only to mark it as
+    // volatile.
+    let cwd = cwd.display().to_string();
+    let attachments = json_to_dynamic(attachments);
+    #[allow(deprecated)]
+    engine.on_var(move |name, _, _| match name {
+        "CWD" => Ok(Some(cwd.clone().into())),
+        "ATTACHMENTS" => Ok(Some(attachments.clone())),
+        _ => Ok(None),
+    });
+
     // Register say() function
     let say_tx = msg_tx.clone();
     engine.register_fn("say", move |text: &str| {
         let _ = say_tx.send(RhaiMessage::Say(text.to_string()));
     });
 
-    // FIXME: In the future, could make this return a bool/error based on the results
-    // Register write_file(path, content)
+    // Register think() function
+    let think_tx = msg_tx.clone();
+    engine.register_fn("think", move |text: &str| {
+        let _ = think_tx.send(RhaiMessage::Think(text.to_string()));
+    });
+
+    // Register confirm(question), which returns whether the user said yes
+    let ask_tx = msg_tx.clone();
+    engine.register_fn(
+        "confirm",
+        move |question: &str| -> Result<bool, Box<EvalAltResult>> {
+            let answer = request_answer(&ask_tx, question, permissions::confirm_options())?;
+            Ok(answer == permissions::YES)
+        },
+    );
+
+    // Register choose(question, options), which returns the id of the option
+    // the user selected
+    let ask_tx = msg_tx.clone();
+    engine.register_fn(
+        "choose",
+        move |question: &str, options: rhai::Array| -> Result<String, Box<EvalAltResult>> {
+            let options = options
+                .into_iter()
+                .map(choice_option)
+                .collect::<Result<Vec<_>, _>>()?;
+            if options.is_empty() {
+                return Err("choose() needs at least one option".into());
+            }
+            request_answer(&ask_tx, question, options)
+        },
+    );
+
+    // Register write_file(path, content), which throws if the write fails
     let write_tx = msg_tx.clone();
-    engine.register_fn("write_file", move |path: &str, content: &str| {
-        let _ = write_tx.send(RhaiMessage::WriteFile {
-            path: path.to_string(),
-            content: content.to_string(),
-        });
+    engine.register_fn(
+        "write_file",
+        move |path: &str, content: &str| -> Result<(), Box<EvalAltResult>> {
+            let (response_tx, response_rx) = std::sync::mpsc::channel();
+            let _ = write_tx.send(RhaiMessage::WriteFile {
+                path: path.to_string(),
+                content: content.to_string(),
+                response_tx,
+            });
+            response_rx
+                .recv()
+                .unwrap_or_else(|_| Err("Channel closed".to_string()))
+                .map_err(Into::into)
+        },
+    );
+
+    // Register read_file(path) and read_file(path, #{ line, limit }), which
+    // throw if the read fails
+    let read_tx = msg_tx.clone();
+    engine.register_fn(
+        "read_file",
+        move |path: &str| -> Result<String, Box<EvalAltResult>> {
+            request_read(&read_tx, path, None, None)
+        },
+    );
+    let read_tx = msg_tx.clone();
+    engine.register_fn(
+        "read_file",
+        move |path: &str, options: rhai::Map| -> Result<String, Box<EvalAltResult>> {
+            let line = line_option(&options, "line")?;
+            let limit = line_option(&options, "limit")?;
+            request_read(&read_tx, path, line, limit)
+        },
+    );
+
+    // Register reset(), which discards the session's variables and functions
+    // once the current script finishes
+    let reset_requested = Arc::new(AtomicBool::new(false));
+    let reset_flag = reset_requested.clone();
+    engine.register_fn("reset", move || {
+        reset_flag.store(true, Ordering::SeqCst);
     });
 
+    // Register terminal module
+    let terminal_module = TerminalModule::new(msg_tx.clone());
+    let module: Module = terminal_module.into();
+    engine.register_static_module("terminal", module.into());
+
+    // Register process module
+    let process_module = ProcessModule::new(msg_tx.clone());
+    let module: Module = process_module.into();
+    engine.register_static_module("process", module.into());
+
+    // Register plan module
+    let plan_module = PlanModule::new(msg_tx.clone());
+    let module: Module = plan_module.into();
+    engine.register_static_module("plan", module.into());
+
     // Register mcp module
     let mcp_module = McpModule::new(msg_tx);
     let module: Module = mcp_module.into();
     engine.register_static_module("mcp", module.into());
 
-    // Execute the script
-    engine.run(script).map_err(|e| e.to_string())
+    // Compile the script on top of the functions defined by earlier prompts
+    let ast = engine
+        .compile(script)
+        .map_err(|e| EvalAltResult::from(e).to_string())?;
+    let ast = state.functions.merge(&ast);
+    state.functions = ast.clone_functions_only();
+
+    // Execute the script against the session scope
+    let result = engine
+        .run_ast_with_scope(&mut state.scope, &ast)
+        .map_err(|e| e.to_string());
+
+    if reset_requested.load(Ordering::SeqCst) {
+        *state = ScriptState::default();
+    }
+
+    result
 }
 
-/// Extract text content from prompt blocks
+/// Ask the async runtime to read `path`, blocking until it responds
+fn request_read(
+    tx: &mpsc::UnboundedSender<RhaiMessage>,
+    path: &str,
+    line: Option<u32>,
+    limit: Option<u32>,
+) -> Result<String, Box<EvalAltResult>> {
+    let (response_tx, response_rx) = std::sync::mpsc::channel();
+    let _ = tx.send(RhaiMessage::ReadFile {
+        path: path.to_string(),
+        line,
+        limit,
+        response_tx,
+    });
+    response_rx
+        .recv()
+        .unwrap_or_else(|_| Err("Channel closed".to_string()))
+        .map_err(Into::into)
+}
+
+/// Ask the user `question`, blocking until they select one of `options`.
+/// Throws if the client cancels the question.
+fn request_answer(
+    tx: &mpsc::UnboundedSender<RhaiMessage>,
+    question: &str,
+    options: Vec<PermissionOption>,
+) -> Result<String, Box<EvalAltResult>> {
+    let (response_tx, response_rx) = std::sync::mpsc::channel();
+    let _ = tx.send(RhaiMessage::Ask {
+        question: question.to_string(),
+        options,
+        response_tx,
+    });
+    response_rx
+        .recv()
+        .unwrap_or_else(|_| Err("Channel closed".to_string()))
+        .map_err(Into::into)
+}
+
+/// Convert one of a script's `choose()` options to a permission option. An
+/// option is either a string, used as its id and name, or a map with an `id`,
+/// a `name` and an optional `kind` (`allow_once` by default).
+fn choice_option(option: Dynamic) -> Result<PermissionOption, Box<EvalAltResult>> {
+    if option.is_string() {
+        let option = option.into_string()?;
+        return Ok(PermissionOption::new(
+            option.clone(),
+            option,
+            PermissionOptionKind::AllowOnce,
+        ));
+    }
+    let Some(option) = option.try_cast::<rhai::Map>() else {
+        return Err("choose() options must be strings or maps".into());
+    };
+    let field = |name: &str| {
+        option
+            .get(name)
+            .and_then(|value| value.clone().into_string().ok())
+    };
+    let (Some(id), Some(name)) = (field("id"), field("name")) else {
+        return Err("choose() options must have a string id and name".into());
+    };
+    let kind = match field("kind") {
+        None => PermissionOptionKind::AllowOnce,
+        Some(kind) => permissions::option_kind(&kind)
+            .ok_or_else(|| format!("Unknown option kind: {}", kind))?,
+    };
+    Ok(PermissionOption::new(id, name, kind))
+}
+
+/// Read the positive integer `name` from a `read_file` options map
+fn line_option(options: &rhai::Map, name: &str) -> Result<Option<u32>, Box<EvalAltResult>> {
+    let Some(value) = options.get(name) else {
+        return Ok(None);
+    };
+    match value.as_int().map(u32::try_from) {
+        Ok(Ok(value)) if value > 0 => Ok(Some(value)),
+        _ => Err(format!("{} must be a positive integer", name).into()),
+    }
+}
+
+/// The `limit` lines of `content` starting at the 1-based `line`, matching what
+/// ACP clients return for `fs/read_text_file`
+fn select_lines(content: &str, line: Option<u32>, limit: Option<u32>) -> String {
+    if line.is_none() && limit.is_none() {
+        return content.to_string();
+    }
+    let skip = line.map_or(0, |line| line as usize - 1);
+    let take = limit.map_or(usize::MAX, |limit| limit as usize);
+    content
+        .split_inclusive('\n')
+        .skip(skip)
+        .take(take)
+        .collect()
+}
+
+/// Capabilities advertised to the client in the initialize response
+fn agent_capabilities() -> AgentCapabilities {
+    AgentCapabilities::new()
+        .load_session(true)
+        .prompt_capabilities(
+            PromptCapabilities::new()
+                .image(true)
+                .audio(true)
+                .embedded_context(true),
+        )
+        .mcp_capabilities(McpCapabilities::new().http(true).sse(true))
+}
+
+/// Reject prompt content that we do not know how to handle. Every content
+/// type that `agent_capabilities` advertises is handled.
+fn check_prompt_supported(blocks: &[ContentBlock]) -> Result<(), sacp::Error> {
+    for block in blocks {
+        match block {
+            ContentBlock::Text(_)
+            | ContentBlock::ResourceLink(_)
+            | ContentBlock::Image(_)
+            | ContentBlock::Audio(_)
+            | ContentBlock::Resource(_) => {}
+            _ => {
+                return Err(sacp::Error::invalid_params()
+                    .data("rhaicp does not support this content type in prompts"));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reject MCP server transports that we do not know how to connect to. Every
+/// transport that `agent_capabilities` advertises is handled.
+fn check_mcp_servers_supported(mcp_servers: &[McpServer]) -> Result<(), sacp::Error> {
+    for mcp_server in mcp_servers {
+        match mcp_server {
+            McpServer::Stdio(_) | McpServer::Http(_) | McpServer::Sse(_) => {}
+            _ => {
+                return Err(sacp::Error::invalid_params()
+                    .data("rhaicp does not support this MCP server transport"));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Extract text content from prompt blocks.
+/// Embedded text resources are included, so a client can attach a script file.
 fn extract_text_from_prompt(blocks: &[ContentBlock]) -> String {
     blocks
         .iter()
         .filter_map(|block| match block {
             ContentBlock::Text(TextContent { text, .. }) => Some(text.clone()),
+            ContentBlock::Resource(EmbeddedResource {
+                resource:
+                    EmbeddedResourceResource::TextResourceContents(TextResourceContents {
+                        text, ..
+                    }),
+                ..
+            }) => Some(text.clone()),
             _ => None,
         })
         .collect::<Vec<_>>()
         .join(" ")
 }
 
+/// Describe the images, audio and binary resources in a prompt, which are not
+/// part of the script, as a JSON array with the same keys as MCP tool content
+fn describe_attachments(blocks: &[ContentBlock]) -> serde_json::Value {
+    blocks
+        .iter()
+        .filter_map(|block| match block {
+            ContentBlock::Image(image) => Some(serde_json::json!({
+                "type": "image",
+                "data": image.data,
+                "mime_type": image.mime_type,
+                "uri": image.uri,
+            })),
+            ContentBlock::Audio(audio) => Some(serde_json::json!({
+                "type": "audio",
+                "data": audio.data,
+                "mime_type": audio.mime_type,
+            })),
+            ContentBlock::Resource(EmbeddedResource {
+                resource: EmbeddedResourceResource::BlobResourceContents(resource),
+                ..
+            }) => Some(serde_json::json!({
+                "type": "resource",
+                "uri": resource.uri,
+                "mime_type": resource.mime_type,
+                "blob": resource.blob,
+            })),
+            _ => None,
+        })
+        .collect()
+}
+
 /// Extract Rhai script from input text
 /// If the text contains `<userRequest>...</userRequest>`, extract that content
 /// Otherwise, treat the entire text as a Rhai script
 fn extract_rhai_script(input: &str) -> String {
     // Try to extract from <userRequest> tags
-    if let Some(start) = input.find("<userRequest>") {
-        if let Some(end) = input.find("</userRequest>") {
-            let content_start = start + "<userRequest>".len();
-            if content_start < end {
-                return input[content_start..end].trim().to_string();
-            }
+    if let Some(start) = input.find("<userRequest>")
+        && let Some(end) = input.find("</userRequest>")
+    {
+        let content_start = start + "<userRequest>".len();
+        if content_start < end {
+            return input[content_start..end].trim().to_string();
         }
     }
 
@@ -468,16 +2103,20 @@ impl Component<sacp::link::AgentToClient> for RhaiAgent {
         self,
         client: impl Component<sacp::link::ClientToAgent>,
     ) -> Result<(), sacp::Error> {
-        AgentToClient::builder()
+        let result = AgentToClient::builder()
             .name("rhaicp")
             .on_receive_request(
-                async |initialize: InitializeRequest, request_cx, _cx| {
-                    tracing::debug!("Received initialize request");
+                {
+                    let agent = self.clone();
+                    async move |initialize: InitializeRequest, request_cx, _cx| {
+                        tracing::debug!("Received initialize request");
 
-                    request_cx.respond(
-                        InitializeResponse::new(initialize.protocol_version)
-                            .agent_capabilities(AgentCapabilities::new()),
-                    )
+                        *agent.client_capabilities.lock().unwrap() = initialize.client_capabilities;
+                        request_cx.respond(
+                            InitializeResponse::new(initialize.protocol_version)
+                                .agent_capabilities(agent_capabilities()),
+                        )
+                    }
                 },
                 sacp::on_receive_request!(),
             )
@@ -493,12 +2132,22 @@ impl Component<sacp::link::AgentToClient> for RhaiAgent {
             .on_receive_request(
                 {
                     let agent = self.clone();
-                    async move |request: LoadSessionRequest, request_cx, _cx| {
-                        agent.handle_load_session(request, request_cx).await
+                    async move |request: LoadSessionRequest, request_cx, cx| {
+                        agent.handle_load_session(request, request_cx, cx).await
                     }
                 },
                 sacp::on_receive_request!(),
             )
+            .on_receive_notification(
+                {
+                    let agent = self.clone();
+                    async move |notification: CancelNotification, _cx| {
+                        agent.cancel_session(&notification.session_id);
+                        Ok(())
+                    }
+                },
+                sacp::on_receive_notification!(),
+            )
             .on_receive_request(
                 {
                     let agent = self.clone();
@@ -514,6 +2163,11 @@ impl Component<sacp::link::AgentToClient> for RhaiAgent {
             )
             .connect_to(client)?
             .serve()
-            .await
+            .await;
+
+        // The connection is gone, so none of the sessions can be used again
+        self.shutdown_mcp_clients().await;
+
+        result
     }
 }
@@ -7,12 +7,27 @@
 //! Rhaicp provides a scriptable agent that:
 //! - Accepts prompts that are either Rhai programs or contain `<userRequest>...</userRequest>` blocks
 //! - Exposes `say(text)` to stream responses back to the client
-//! - Exposes `mcp::list_tools(server)` and `mcp::call_tool(server, tool, args)` for MCP access
+//! - Exposes `think(text)` to stream thoughts that clients show apart from the
+//!   response
+//! - Exposes `confirm(question)` and `choose(question, options)` to ask the
+//!   user questions mid-run
+//! - Exposes `plan::set(entries)`, `plan::start(index)` and
+//!   `plan::complete(index)` to show the client a checklist of the script's work
+//! - Exposes `read_file(path)` and `write_file(path, content)` to read and
+//!   write files, through the client when it can
+//! - Exposes `terminal::run(command, args)` to run commands in the client's terminals
+//! - Exposes `process::exec(command, args)` to run allowed commands as local
+//!   processes
+//! - Exposes `mcp::list_tools(server)`, `mcp::describe_tools(server)`,
+//!   `mcp::call_tool(server, tool, args)` and
+//!   `mcp::call_tool_result(server, tool, args)` for MCP access
 
 use anyhow::Result;
 use clap::Parser;
-use rhaicp::RhaiAgent;
+use rhaicp::{FsPolicy, PermissionPolicy, RhaiAgent, SessionStore};
 use sacp::Component;
+use std::path::PathBuf;
+use std::time::Duration;
 use tracing_subscriber::{EnvFilter, layer::SubscriberExt, util::SubscriberInitExt};
 
 #[derive(Parser, Debug)]
@@ -22,10 +37,50 @@ struct Args {
     #[arg(short, long)]
     debug: bool,
 
+    /// Directory where sessions are saved for `session/load`
+    /// (defaults to `$XDG_DATA_HOME/rhaicp/sessions`)
+    #[arg(long)]
+    session_dir: Option<PathBuf>,
+
+    /// Timeout for MCP requests, in milliseconds, for servers that do not
+    /// configure their own (defaults to 60 seconds)
+    #[arg(long)]
+    mcp_timeout_ms: Option<u64>,
+
+    /// Directory outside the session cwd that scripts may read and write
+    /// (can be repeated)
+    #[arg(long = "allow-root", value_name = "DIR")]
+    allow_roots: Vec<PathBuf>,
+
+    /// Executable that scripts may run locally with `process::exec`
+    /// (can be repeated)
+    #[arg(long = "allow-command", value_name = "NAME")]
+    allow_commands: Vec<String>,
+
+    /// Let scripts read and write any file the agent can
+    #[arg(long, conflicts_with = "allow_roots")]
+    unrestricted_fs: bool,
+
+    /// When to ask the client for permission before scripts write files, run
+    /// commands or call MCP tools
+    #[arg(long, value_enum, default_value_t = Permissions::Never)]
+    permissions: Permissions,
+
     #[command(subcommand)]
     command: Command,
 }
 
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum Permissions {
+    /// Never ask
+    Never,
+    /// Ask before every file write, command and MCP tool call
+    Always,
+    /// Ask before every file write and command, and before calls to MCP tools
+    /// that are not annotated as read-only
+    Annotations,
+}
+
 #[derive(clap::Subcommand, Debug)]
 enum Command {
     /// Run as ACP agent over stdio
@@ -55,7 +110,29 @@ async fn main() -> Result<()> {
     match args.command {
         Command::Acp => {
             tracing::info!("Rhaicp starting");
-            RhaiAgent::new().serve(sacp_tokio::Stdio::new()).await?;
+            let mut agent = RhaiAgent::new();
+            if let Some(session_dir) = args.session_dir.or_else(SessionStore::default_dir) {
+                tracing::info!("Saving sessions to {}", session_dir.display());
+                agent = agent.with_session_store(SessionStore::new(session_dir));
+            }
+            if let Some(timeout_ms) = args.mcp_timeout_ms {
+                agent = agent.with_mcp_timeout(Duration::from_millis(timeout_ms));
+            }
+            let fs_policy = if args.unrestricted_fs {
+                FsPolicy::unrestricted()
+            } else {
+                args.allow_roots
+                    .into_iter()
+                    .fold(FsPolicy::new(), FsPolicy::allow_root)
+            };
+            agent = agent.with_fs_policy(fs_policy);
+            agent = agent.with_allowed_commands(args.allow_commands);
+            agent = agent.with_permission_policy(match args.permissions {
+                Permissions::Never => PermissionPolicy::Never,
+                Permissions::Always => PermissionPolicy::Always,
+                Permissions::Annotations => PermissionPolicy::Annotations,
+            });
+            agent.serve(sacp_tokio::Stdio::new()).await?;
         }
     }
 
@@ -0,0 +1,76 @@
+//! Failures of MCP requests made on behalf of scripts
+
+use rmcp::service::ServiceError;
+
+/// What went wrong with an MCP request
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum McpErrorKind {
+    /// The session has no MCP server with the requested name
+    NotFound,
+    /// Connecting to the server or exchanging messages with it failed
+    Transport,
+    /// The server answered the request with a JSON-RPC error
+    Server,
+    /// The tool ran and reported an error in its result
+    Tool,
+    /// The server did not answer in time
+    Timeout,
+    /// The prompt was cancelled while the request was in flight
+    Cancelled,
+    /// The user did not allow the tool call
+    PermissionDenied,
+}
+
+impl McpErrorKind {
+    /// The name scripts see in the `kind` field of a thrown error
+    pub fn as_str(self) -> &'static str {
+        match self {
+            McpErrorKind::NotFound => "not_found",
+            McpErrorKind::Transport => "transport",
+            McpErrorKind::Server => "server",
+            McpErrorKind::Tool => "tool",
+            McpErrorKind::Timeout => "timeout",
+            McpErrorKind::Cancelled => "cancelled",
+            McpErrorKind::PermissionDenied => "permission_denied",
+        }
+    }
+}
+
+/// A failed MCP request
+#[derive(Clone, Debug)]
+pub struct McpError {
+    pub kind: McpErrorKind,
+    pub message: String,
+}
+
+impl McpError {
+    pub fn new(kind: McpErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+        }
+    }
+
+    /// Classify an error from an rmcp request, prefixing its message with `context`
+    pub fn from_service(context: &str, error: ServiceError) -> Self {
+        let kind = match &error {
+            ServiceError::McpError(_) => McpErrorKind::Server,
+            ServiceError::Timeout { .. } => McpErrorKind::Timeout,
+            ServiceError::Cancelled { .. } => McpErrorKind::Cancelled,
+            _ => McpErrorKind::Transport,
+        };
+        let message = match error {
+            // rmcp reports the time left when the request was sent, not the
+            // configured timeout, so leave the duration out
+            ServiceError::Timeout { .. } => format!("{}: timed out", context),
+            error => format!("{}: {}", context, error),
+        };
+        Self::new(kind, message)
+    }
+}
+
+impl std::fmt::Display for McpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
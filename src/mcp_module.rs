@@ -1,9 +1,14 @@
-//! Rhai module providing MCP tool access via `mcp::list_tools` and `mcp::call_tool`
+//! Rhai module providing MCP tool access via `mcp::list_tools`, `mcp::describe_tools`,
+//! `mcp::call_tool` and `mcp::call_tool_result`
 
 use crate::RhaiMessage;
-use rhai::{Dynamic, FuncRegistration, Module};
+use crate::mcp_error::{McpError, McpErrorKind};
+use rhai::{Dynamic, EvalAltResult, FuncRegistration, Module, Position};
+use std::time::Duration;
 use tokio::sync::mpsc;
 
+type RhaiResult = Result<Dynamic, Box<EvalAltResult>>;
+
 /// MCP module for Rhai that provides tool access
 pub struct McpModule {
     msg_tx: mpsc::UnboundedSender<RhaiMessage>,
@@ -19,35 +24,43 @@ impl From<McpModule> for Module {
     fn from(mcp: McpModule) -> Self {
         let mut module = Module::new();
 
+        // Every function also accepts a trailing options map, e.g.
+        // `#{ timeout_ms: 5000 }` to override the server's request timeout.
+
         // list_tools(server) -> Array of tool names
         let tx = mcp.msg_tx.clone();
         FuncRegistration::new("list_tools")
             .in_global_namespace()
-            .set_into_module(&mut module, move |server: &str| -> Dynamic {
-                let (response_tx, response_rx) = std::sync::mpsc::channel();
-
-                let _ = tx.send(RhaiMessage::ListTools {
-                    server: server.to_string(),
-                    response_tx,
-                });
-
-                // Block waiting for the async runtime to respond
-                match response_rx.recv() {
-                    Ok(Ok(tools)) => {
-                        // Convert Vec<String> to Rhai Array
-                        tools
-                            .into_iter()
-                            .map(Dynamic::from)
-                            .collect::<Vec<_>>()
-                            .into()
-                    }
-                    Ok(Err(e)) => {
-                        // Return error as a string - Rhai can check for this
-                        Dynamic::from(format!("ERROR: {}", e))
-                    }
-                    Err(_) => Dynamic::from("ERROR: Channel closed"),
-                }
+            .set_into_module(&mut module, move |server: &str| -> RhaiResult {
+                list_tools(&tx, server, None)
             });
+        let tx = mcp.msg_tx.clone();
+        FuncRegistration::new("list_tools")
+            .in_global_namespace()
+            .set_into_module(
+                &mut module,
+                move |server: &str, options: rhai::Map| -> RhaiResult {
+                    list_tools(&tx, server, timeout_option(&options)?)
+                },
+            );
+
+        // describe_tools(server) -> Array of maps with each tool's name, title,
+        // description, input_schema, output_schema and annotations
+        let tx = mcp.msg_tx.clone();
+        FuncRegistration::new("describe_tools")
+            .in_global_namespace()
+            .set_into_module(&mut module, move |server: &str| -> RhaiResult {
+                describe_tools(&tx, server, None)
+            });
+        let tx = mcp.msg_tx.clone();
+        FuncRegistration::new("describe_tools")
+            .in_global_namespace()
+            .set_into_module(
+                &mut module,
+                move |server: &str, options: rhai::Map| -> RhaiResult {
+                    describe_tools(&tx, server, timeout_option(&options)?)
+                },
+            );
 
         // call_tool(server, tool, args) -> Dynamic result
         // args should be a Rhai Map that we convert to JSON
@@ -56,28 +69,38 @@ impl From<McpModule> for Module {
             .in_global_namespace()
             .set_into_module(
                 &mut module,
-                move |server: &str, tool: &str, args: Dynamic| -> Dynamic {
-                    let (response_tx, response_rx) = std::sync::mpsc::channel();
-
-                    // Convert Rhai Dynamic to serde_json::Value
-                    let json_args = dynamic_to_json(&args);
-
-                    let _ = tx.send(RhaiMessage::CallTool {
-                        server: server.to_string(),
-                        tool: tool.to_string(),
-                        args: json_args,
-                        response_tx,
-                    });
-
-                    // Block waiting for the async runtime to respond
-                    match response_rx.recv() {
-                        Ok(Ok(result)) => {
-                            // Convert JSON result back to Dynamic
-                            json_to_dynamic(&result)
-                        }
-                        Ok(Err(e)) => Dynamic::from(format!("ERROR: {}", e)),
-                        Err(_) => Dynamic::from("ERROR: Channel closed"),
-                    }
+                move |server: &str, tool: &str, args: Dynamic| -> RhaiResult {
+                    call_tool(&tx, server, tool, &args, None)
+                },
+            );
+        let tx = mcp.msg_tx.clone();
+        FuncRegistration::new("call_tool")
+            .in_global_namespace()
+            .set_into_module(
+                &mut module,
+                move |server: &str, tool: &str, args: Dynamic, options: rhai::Map| -> RhaiResult {
+                    call_tool(&tx, server, tool, &args, timeout_option(&options)?)
+                },
+            );
+
+        // call_tool_result(server, tool, args) -> Map with every `content` block,
+        // `structured_content`, `is_error` and the simple `value`
+        let tx = mcp.msg_tx.clone();
+        FuncRegistration::new("call_tool_result")
+            .in_global_namespace()
+            .set_into_module(
+                &mut module,
+                move |server: &str, tool: &str, args: Dynamic| -> RhaiResult {
+                    call_tool_result(&tx, server, tool, &args, None)
+                },
+            );
+        let tx = mcp.msg_tx.clone();
+        FuncRegistration::new("call_tool_result")
+            .in_global_namespace()
+            .set_into_module(
+                &mut module,
+                move |server: &str, tool: &str, args: Dynamic, options: rhai::Map| -> RhaiResult {
+                    call_tool_result(&tx, server, tool, &args, timeout_option(&options)?)
                 },
             );
 
@@ -85,8 +108,149 @@ impl From<McpModule> for Module {
     }
 }
 
+/// The names of the tools of `server`
+fn list_tools(
+    tx: &mpsc::UnboundedSender<RhaiMessage>,
+    server: &str,
+    timeout: Option<Duration>,
+) -> RhaiResult {
+    let tools = request_tools(tx, server, timeout).map_err(|e| mcp_error(e, server, None))?;
+    Ok(tools
+        .iter()
+        .filter_map(|tool| tool["name"].as_str())
+        .map(|name| Dynamic::from(name.to_string()))
+        .collect::<Vec<_>>()
+        .into())
+}
+
+/// The full descriptions of the tools of `server`
+fn describe_tools(
+    tx: &mpsc::UnboundedSender<RhaiMessage>,
+    server: &str,
+    timeout: Option<Duration>,
+) -> RhaiResult {
+    let tools = request_tools(tx, server, timeout).map_err(|e| mcp_error(e, server, None))?;
+    Ok(tools.iter().map(json_to_dynamic).collect::<Vec<_>>().into())
+}
+
+/// Call `tool`, returning its simple value and throwing if it reports an error
+fn call_tool(
+    tx: &mpsc::UnboundedSender<RhaiMessage>,
+    server: &str,
+    tool: &str,
+    args: &Dynamic,
+    timeout: Option<Duration>,
+) -> RhaiResult {
+    let result = request_tool_call(tx, server, tool, args, timeout)
+        .map_err(|e| mcp_error(e, server, Some(tool)))?;
+    if result["is_error"] == true {
+        let error = McpError::new(McpErrorKind::Tool, tool_error_message(&result));
+        return Err(mcp_error(error, server, Some(tool)));
+    }
+    // Convert the simple result value back to Dynamic
+    Ok(result.get("value").map_or(Dynamic::UNIT, json_to_dynamic))
+}
+
+/// Call `tool`, returning its whole result
+fn call_tool_result(
+    tx: &mpsc::UnboundedSender<RhaiMessage>,
+    server: &str,
+    tool: &str,
+    args: &Dynamic,
+    timeout: Option<Duration>,
+) -> RhaiResult {
+    let result = request_tool_call(tx, server, tool, args, timeout)
+        .map_err(|e| mcp_error(e, server, Some(tool)))?;
+    Ok(json_to_dynamic(&result))
+}
+
+/// Read the `timeout_ms` entry of a call's options map
+pub(crate) fn timeout_option(options: &rhai::Map) -> Result<Option<Duration>, Box<EvalAltResult>> {
+    let Some(timeout_ms) = options.get("timeout_ms") else {
+        return Ok(None);
+    };
+    match timeout_ms.as_int() {
+        Ok(timeout_ms) if timeout_ms > 0 => Ok(Some(Duration::from_millis(timeout_ms as u64))),
+        _ => Err("timeout_ms must be a positive integer".into()),
+    }
+}
+
+/// Build the exception thrown to scripts for a failed MCP request: a map with
+/// the error's `kind`, the `server` and `tool` involved, and a `message`
+fn mcp_error(error: McpError, server: &str, tool: Option<&str>) -> Box<EvalAltResult> {
+    let mut map = rhai::Map::new();
+    map.insert("kind".into(), error.kind.as_str().into());
+    map.insert("server".into(), server.into());
+    map.insert(
+        "tool".into(),
+        tool.map_or(Dynamic::UNIT, |tool| tool.into()),
+    );
+    map.insert("message".into(), error.message.into());
+    EvalAltResult::ErrorRuntime(map.into(), Position::NONE).into()
+}
+
+/// The text a tool reported alongside its error flag
+fn tool_error_message(result: &serde_json::Value) -> String {
+    let text = result["content"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|block| block["text"].as_str())
+        .collect::<Vec<_>>()
+        .join("\n");
+    if text.is_empty() {
+        "Tool reported an error".to_string()
+    } else {
+        text
+    }
+}
+
+/// Ask the async runtime for the tools of `server`, blocking until it responds
+fn request_tools(
+    tx: &mpsc::UnboundedSender<RhaiMessage>,
+    server: &str,
+    timeout: Option<Duration>,
+) -> Result<Vec<serde_json::Value>, McpError> {
+    let (response_tx, response_rx) = std::sync::mpsc::channel();
+
+    let _ = tx.send(RhaiMessage::ListTools {
+        server: server.to_string(),
+        timeout,
+        response_tx,
+    });
+
+    response_rx.recv().unwrap_or_else(|_| Err(channel_closed()))
+}
+
+/// Ask the async runtime to call `tool` on `server`, blocking until it responds
+fn request_tool_call(
+    tx: &mpsc::UnboundedSender<RhaiMessage>,
+    server: &str,
+    tool: &str,
+    args: &Dynamic,
+    timeout: Option<Duration>,
+) -> Result<serde_json::Value, McpError> {
+    let (response_tx, response_rx) = std::sync::mpsc::channel();
+
+    let _ = tx.send(RhaiMessage::CallTool {
+        server: server.to_string(),
+        tool: tool.to_string(),
+        // Convert Rhai Dynamic to serde_json::Value
+        args: dynamic_to_json(args),
+        timeout,
+        response_tx,
+    });
+
+    response_rx.recv().unwrap_or_else(|_| Err(channel_closed()))
+}
+
+/// The error for a request whose response channel closed before it answered
+fn channel_closed() -> McpError {
+    McpError::new(McpErrorKind::Transport, "Channel closed")
+}
+
 /// Convert a Rhai Dynamic value to serde_json::Value
-fn dynamic_to_json(value: &Dynamic) -> serde_json::Value {
+pub(crate) fn dynamic_to_json(value: &Dynamic) -> serde_json::Value {
     if value.is_unit() {
         serde_json::Value::Null
     } else if value.is_bool() {
@@ -118,7 +282,7 @@ fn dynamic_to_json(value: &Dynamic) -> serde_json::Value {
 }
 
 /// Convert a serde_json::Value to Rhai Dynamic
-fn json_to_dynamic(value: &serde_json::Value) -> Dynamic {
+pub(crate) fn json_to_dynamic(value: &serde_json::Value) -> Dynamic {
     match value {
         serde_json::Value::Null => Dynamic::UNIT,
         serde_json::Value::Bool(b) => Dynamic::from(*b),
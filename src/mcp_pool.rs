@@ -0,0 +1,273 @@
+//! Long-lived MCP client connections shared by every prompt in a session
+
+use rmcp::model::ToolAnnotations;
+use rmcp::service::{NotificationContext, Peer, RoleClient, RunningService};
+use rmcp::{ClientHandler, ServiceExt};
+use sacp::schema::{HttpHeader, McpServer};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+use crate::mcp_error::{McpError, McpErrorKind};
+use crate::permissions::PermissionPolicy;
+use crate::sse_transport::SseClientTransport;
+
+type McpClient = RunningService<RoleClient, PoolClientHandler>;
+
+/// The annotations of a server's tools, by tool name, as last listed
+#[derive(Default)]
+pub(crate) struct ToolAnnotationCache {
+    /// Bumped whenever the server says its tool list changed, so a listing
+    /// that was already under way is not cached
+    generation: u64,
+    annotations: Option<HashMap<String, Option<ToolAnnotations>>>,
+}
+
+impl ToolAnnotationCache {
+    /// The cached annotations of `tool_name`, or `None` if the tools need to be
+    /// listed first
+    pub fn get(&self, tool_name: &str) -> Option<Option<ToolAnnotations>> {
+        let annotations = self.annotations.as_ref()?;
+        Some(annotations.get(tool_name).cloned().flatten())
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Cache `annotations`, listed when the cache was at `generation`
+    pub fn store(
+        &mut self,
+        generation: u64,
+        annotations: HashMap<String, Option<ToolAnnotations>>,
+    ) {
+        if generation == self.generation {
+            self.annotations = Some(annotations);
+        }
+    }
+}
+
+pub(crate) type SharedToolAnnotations = Arc<std::sync::Mutex<ToolAnnotationCache>>;
+
+/// Client handler that forgets a server's cached tool annotations when the
+/// server says its tool list changed
+#[derive(Default)]
+struct PoolClientHandler {
+    tool_annotations: SharedToolAnnotations,
+}
+
+impl ClientHandler for PoolClientHandler {
+    async fn on_tool_list_changed(&self, _context: NotificationContext<RoleClient>) {
+        let mut cache = self.tool_annotations.lock().unwrap();
+        cache.generation += 1;
+        cache.annotations = None;
+    }
+}
+
+/// Connected MCP clients for one session, keyed by server name.
+///
+/// Clients are connected on first use and reconnected if their transport
+/// has closed, e.g. because a stdio server crashed.
+#[derive(Default)]
+pub(crate) struct McpClientPool {
+    clients: Mutex<HashMap<String, McpClient>>,
+}
+
+impl McpClientPool {
+    /// Get a client for the server named `server_name`, connecting to it if needed
+    pub async fn get(
+        &self,
+        mcp_servers: &[McpServer],
+        server_name: &str,
+    ) -> Result<Peer<RoleClient>, McpError> {
+        self.with_client(mcp_servers, server_name, |client| client.peer().clone())
+            .await
+    }
+
+    /// Get a client for the server named `server_name` along with the cached
+    /// annotations of its tools, connecting to it if needed
+    pub async fn get_with_tool_annotations(
+        &self,
+        mcp_servers: &[McpServer],
+        server_name: &str,
+    ) -> Result<(Peer<RoleClient>, SharedToolAnnotations), McpError> {
+        self.with_client(mcp_servers, server_name, |client| {
+            (
+                client.peer().clone(),
+                client.service().tool_annotations.clone(),
+            )
+        })
+        .await
+    }
+
+    async fn with_client<T>(
+        &self,
+        mcp_servers: &[McpServer],
+        server_name: &str,
+        f: impl FnOnce(&McpClient) -> T,
+    ) -> Result<T, McpError> {
+        let mut clients = self.clients.lock().await;
+
+        if let Some(client) = clients.get(server_name) {
+            if !client.is_transport_closed() {
+                return Ok(f(client));
+            }
+            tracing::info!("MCP server '{}' disconnected, reconnecting", server_name);
+            clients.remove(server_name);
+        }
+
+        let mcp_server = find_server(mcp_servers, server_name).ok_or_else(|| {
+            McpError::new(
+                McpErrorKind::NotFound,
+                format!("MCP server '{}' not found", server_name),
+            )
+        })?;
+
+        let client = connect(mcp_server)
+            .await
+            .map_err(|e| McpError::new(McpErrorKind::Transport, e))?;
+        let result = f(&client);
+        clients.insert(server_name.to_string(), client);
+        Ok(result)
+    }
+
+    /// Disconnect every client in the pool
+    pub async fn shutdown(&self) {
+        let clients = std::mem::take(&mut *self.clients.lock().await);
+        for (server_name, client) in clients {
+            tracing::debug!("Disconnecting from MCP server '{}'", server_name);
+            if let Err(e) = client.cancel().await {
+                tracing::warn!(?server_name, ?e, "Failed to shut down MCP client");
+            }
+        }
+    }
+}
+
+/// Find the server named `server_name` in a session's configuration
+fn find_server<'a>(mcp_servers: &'a [McpServer], server_name: &str) -> Option<&'a McpServer> {
+    mcp_servers.iter().find(|s| match s {
+        McpServer::Stdio(stdio) => stdio.name == server_name,
+        McpServer::Http(http) => http.name == server_name,
+        McpServer::Sse(sse) => sse.name == server_name,
+        _ => false,
+    })
+}
+
+/// The `"_meta": { "rhaicp": ... }` settings of the server named `server_name`
+fn rhaicp_meta<'a>(mcp_servers: &'a [McpServer], server_name: &str) -> Option<&'a Value> {
+    let meta = match find_server(mcp_servers, server_name)? {
+        McpServer::Stdio(stdio) => stdio.meta.as_ref(),
+        McpServer::Http(http) => http.meta.as_ref(),
+        McpServer::Sse(sse) => sse.meta.as_ref(),
+        _ => None,
+    }?;
+    meta.get("rhaicp")
+}
+
+/// The request timeout configured for the server named `server_name`, read from
+/// `"_meta": { "rhaicp": { "timeoutMs": ... } }` in its configuration
+pub(crate) fn configured_timeout(mcp_servers: &[McpServer], server_name: &str) -> Option<Duration> {
+    let timeout_ms = rhaicp_meta(mcp_servers, server_name)?
+        .get("timeoutMs")?
+        .as_u64()?;
+    Some(Duration::from_millis(timeout_ms))
+}
+
+/// When to ask before calling the tools of the server named `server_name`, read
+/// from `"_meta": { "rhaicp": { "permissions": ... } }` in its configuration
+pub(crate) fn configured_permissions(
+    mcp_servers: &[McpServer],
+    server_name: &str,
+) -> Option<PermissionPolicy> {
+    let name = rhaicp_meta(mcp_servers, server_name)?
+        .get("permissions")?
+        .as_str()?;
+    PermissionPolicy::from_name(name)
+}
+
+/// Start the transport for `mcp_server` and perform the MCP handshake
+async fn connect(mcp_server: &McpServer) -> Result<McpClient, String> {
+    match mcp_server {
+        McpServer::Stdio(stdio) => {
+            use rmcp::transport::{ConfigureCommandExt, TokioChildProcess};
+            use tokio::process::Command;
+
+            let transport = TokioChildProcess::new(Command::new(&stdio.command).configure(|cmd| {
+                cmd.args(&stdio.args);
+                for env_var in &stdio.env {
+                    cmd.env(&env_var.name, &env_var.value);
+                }
+            }))
+            .map_err(|e| format!("Failed to spawn MCP server: {}", e))?;
+
+            PoolClientHandler::default()
+                .serve(transport)
+                .await
+                .map_err(|e| format!("Failed to connect to MCP server: {}", e))
+        }
+        McpServer::Http(http) => {
+            use rmcp::transport::StreamableHttpClientTransport;
+            use rmcp::transport::streamable_http_client::StreamableHttpClientTransportConfig;
+
+            tracing::debug!(
+                url = %http.url,
+                headers = ?header_names(&http.headers),
+                "Connecting to HTTP MCP server"
+            );
+            let transport = StreamableHttpClientTransport::with_client(
+                http_client(&http.headers)?,
+                StreamableHttpClientTransportConfig::with_uri(http.url.clone()),
+            );
+
+            PoolClientHandler::default()
+                .serve(transport)
+                .await
+                .map_err(|e| format!("Failed to connect to HTTP MCP server: {}", e))
+        }
+        McpServer::Sse(sse) => {
+            tracing::debug!(
+                url = %sse.url,
+                headers = ?header_names(&sse.headers),
+                "Connecting to SSE MCP server"
+            );
+            let transport = SseClientTransport::connect(http_client(&sse.headers)?, &sse.url)
+                .await
+                .map_err(|e| format!("Failed to open SSE stream: {}", e))?;
+
+            PoolClientHandler::default()
+                .serve(transport)
+                .await
+                .map_err(|e| format!("Failed to connect to SSE MCP server: {}", e))
+        }
+        _ => Err("Unsupported MCP server transport".to_string()),
+    }
+}
+
+/// Build an HTTP client that sends `headers` with every request.
+/// Header values often hold credentials, so they are marked sensitive to keep
+/// them out of debug output.
+fn http_client(headers: &[HttpHeader]) -> Result<reqwest::Client, String> {
+    use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+
+    let mut header_map = HeaderMap::new();
+    for header in headers {
+        let name = HeaderName::try_from(header.name.as_str())
+            .map_err(|e| format!("Invalid HTTP header name '{}': {}", header.name, e))?;
+        let mut value = HeaderValue::try_from(header.value.as_str())
+            .map_err(|e| format!("Invalid value for HTTP header '{}': {}", header.name, e))?;
+        value.set_sensitive(true);
+        header_map.append(name, value);
+    }
+
+    reqwest::Client::builder()
+        .default_headers(header_map)
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))
+}
+
+/// The names of `headers`, for logging without exposing their values
+fn header_names(headers: &[HttpHeader]) -> Vec<&str> {
+    headers.iter().map(|h| h.name.as_str()).collect()
+}
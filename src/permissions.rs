@@ -0,0 +1,96 @@
+//! Asking the client for permission before scripts have side effects
+
+use rmcp::model::ToolAnnotations;
+use sacp::schema::{PermissionOption, PermissionOptionKind, RequestPermissionOutcome};
+
+/// When to ask the client for permission before a script writes a file, runs
+/// a command or calls an MCP tool
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PermissionPolicy {
+    /// Never ask
+    #[default]
+    Never,
+    /// Ask before every file write, command and MCP tool call
+    Always,
+    /// Ask before every file write and command, and before calls to MCP tools
+    /// that are not annotated as read-only
+    Annotations,
+}
+
+impl PermissionPolicy {
+    /// The policy called `name` in a server's `_meta`
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "never" => Some(Self::Never),
+            "always" => Some(Self::Always),
+            "annotations" => Some(Self::Annotations),
+            _ => None,
+        }
+    }
+}
+
+const ALLOW_ONCE: &str = "allow-once";
+const ALLOW_ALWAYS: &str = "allow-always";
+const REJECT: &str = "reject";
+
+/// What the user chose when asked for permission
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Decision {
+    AllowOnce,
+    /// Allow this and later requests for the same action in the session
+    AllowAlways,
+    Reject,
+}
+
+/// The options offered in a `session/request_permission` request
+pub(crate) fn permission_options() -> Vec<PermissionOption> {
+    vec![
+        PermissionOption::new(ALLOW_ONCE, "Allow", PermissionOptionKind::AllowOnce),
+        PermissionOption::new(
+            ALLOW_ALWAYS,
+            "Always allow",
+            PermissionOptionKind::AllowAlways,
+        ),
+        PermissionOption::new(REJECT, "Reject", PermissionOptionKind::RejectOnce),
+    ]
+}
+
+/// The id of the option `confirm()` returns `true` for
+pub(crate) const YES: &str = "yes";
+
+/// The options offered when a script calls `confirm()`
+pub(crate) fn confirm_options() -> Vec<PermissionOption> {
+    vec![
+        PermissionOption::new(YES, "Yes", PermissionOptionKind::AllowOnce),
+        PermissionOption::new("no", "No", PermissionOptionKind::RejectOnce),
+    ]
+}
+
+/// The option kind called `name` in a script's `choose()` options
+pub(crate) fn option_kind(name: &str) -> Option<PermissionOptionKind> {
+    match name {
+        "allow_once" => Some(PermissionOptionKind::AllowOnce),
+        "allow_always" => Some(PermissionOptionKind::AllowAlways),
+        "reject_once" => Some(PermissionOptionKind::RejectOnce),
+        "reject_always" => Some(PermissionOptionKind::RejectAlways),
+        _ => None,
+    }
+}
+
+/// Whether a tool's annotations promise that it does not modify anything.
+/// Tools without annotations may be destructive, as the MCP spec says.
+pub(crate) fn is_read_only(annotations: Option<&ToolAnnotations>) -> bool {
+    annotations.is_some_and(|annotations| annotations.read_only_hint == Some(true))
+}
+
+/// The user's decision, treating a cancelled request as a rejection
+pub(crate) fn decision(outcome: &RequestPermissionOutcome) -> Decision {
+    match outcome {
+        RequestPermissionOutcome::Selected(selected) => match &*selected.option_id.0 {
+            ALLOW_ONCE => Decision::AllowOnce,
+            ALLOW_ALWAYS => Decision::AllowAlways,
+            _ => Decision::Reject,
+        },
+        _ => Decision::Reject,
+    }
+}
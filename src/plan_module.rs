@@ -0,0 +1,122 @@
+//! Rhai module for showing the client a plan of the script's work via
+//! `plan::set`, `plan::start` and `plan::complete`
+
+use crate::RhaiMessage;
+use rhai::{EvalAltResult, FuncRegistration, Module};
+use sacp::schema::{PlanEntry, PlanEntryPriority, PlanEntryStatus};
+use tokio::sync::mpsc;
+
+/// Plan module for Rhai that sends the session's plan to the client
+pub struct PlanModule {
+    msg_tx: mpsc::UnboundedSender<RhaiMessage>,
+}
+
+impl PlanModule {
+    pub fn new(msg_tx: mpsc::UnboundedSender<RhaiMessage>) -> Self {
+        Self { msg_tx }
+    }
+}
+
+impl From<PlanModule> for Module {
+    fn from(plan: PlanModule) -> Self {
+        let mut module = Module::new();
+
+        // set([#{ content, priority, status }]) replaces the plan
+        let tx = plan.msg_tx.clone();
+        FuncRegistration::new("set").set_into_module(
+            &mut module,
+            move |entries: rhai::Array| -> Result<(), Box<EvalAltResult>> {
+                let entries = entries
+                    .into_iter()
+                    .map(plan_entry)
+                    .collect::<Result<_, _>>()?;
+                let _ = tx.send(RhaiMessage::SetPlan(entries));
+                Ok(())
+            },
+        );
+
+        // start(index) marks an entry as in progress
+        let tx = plan.msg_tx.clone();
+        FuncRegistration::new("start").set_into_module(
+            &mut module,
+            move |index: rhai::INT| -> Result<(), Box<EvalAltResult>> {
+                update_entry(&tx, index, PlanEntryStatus::InProgress)
+            },
+        );
+
+        // complete(index) marks an entry as completed
+        let tx = plan.msg_tx.clone();
+        FuncRegistration::new("complete").set_into_module(
+            &mut module,
+            move |index: rhai::INT| -> Result<(), Box<EvalAltResult>> {
+                update_entry(&tx, index, PlanEntryStatus::Completed)
+            },
+        );
+
+        module
+    }
+}
+
+/// Set the status of the plan entry at `index`, blocking until the agent has
+/// sent the updated plan
+fn update_entry(
+    tx: &mpsc::UnboundedSender<RhaiMessage>,
+    index: rhai::INT,
+    status: PlanEntryStatus,
+) -> Result<(), Box<EvalAltResult>> {
+    let Ok(index) = usize::try_from(index) else {
+        return Err(format!("The plan has no entry {}", index).into());
+    };
+    let (response_tx, response_rx) = std::sync::mpsc::channel();
+
+    let _ = tx.send(RhaiMessage::UpdatePlanEntry {
+        index,
+        status,
+        response_tx,
+    });
+
+    response_rx
+        .recv()
+        .unwrap_or_else(|_| Err("Channel closed".to_string()))?;
+    Ok(())
+}
+
+/// Convert a script's `#{ content, priority, status }` map to a plan entry.
+/// Entries are medium priority and pending unless they say otherwise.
+fn plan_entry(entry: rhai::Dynamic) -> Result<PlanEntry, Box<EvalAltResult>> {
+    let Some(entry) = entry.try_cast::<rhai::Map>() else {
+        return Err("plan entries must be maps".into());
+    };
+    let Some(content) = entry
+        .get("content")
+        .and_then(|content| content.clone().into_string().ok())
+    else {
+        return Err("plan entries must have a string content".into());
+    };
+
+    let priority = match string_field(&entry, "priority")?.as_deref() {
+        None | Some("medium") => PlanEntryPriority::Medium,
+        Some("high") => PlanEntryPriority::High,
+        Some("low") => PlanEntryPriority::Low,
+        Some(other) => return Err(format!("Unknown plan entry priority: {}", other).into()),
+    };
+    let status = match string_field(&entry, "status")?.as_deref() {
+        None | Some("pending") => PlanEntryStatus::Pending,
+        Some("in_progress") => PlanEntryStatus::InProgress,
+        Some("completed") => PlanEntryStatus::Completed,
+        Some(other) => return Err(format!("Unknown plan entry status: {}", other).into()),
+    };
+
+    Ok(PlanEntry::new(content, priority, status))
+}
+
+/// Read an optional string entry of a plan entry's map
+fn string_field(entry: &rhai::Map, name: &str) -> Result<Option<String>, Box<EvalAltResult>> {
+    let Some(value) = entry.get(name) else {
+        return Ok(None);
+    };
+    match value.clone().into_string() {
+        Ok(value) => Ok(Some(value)),
+        Err(_) => Err(format!("plan entry {} must be a string", name).into()),
+    }
+}
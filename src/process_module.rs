@@ -0,0 +1,84 @@
+//! Rhai module for running local processes via `process::exec`, for clients
+//! without terminals
+
+use crate::RhaiMessage;
+use crate::mcp_module::{json_to_dynamic, timeout_option};
+use crate::terminal_module::{cwd_option, env_option, string_args};
+use rhai::{Dynamic, EvalAltResult, FuncRegistration, Module};
+use tokio::sync::mpsc;
+
+type RhaiResult = Result<Dynamic, Box<EvalAltResult>>;
+
+/// Process module for Rhai that runs allowed executables on the agent's machine
+pub struct ProcessModule {
+    msg_tx: mpsc::UnboundedSender<RhaiMessage>,
+}
+
+impl ProcessModule {
+    pub fn new(msg_tx: mpsc::UnboundedSender<RhaiMessage>) -> Self {
+        Self { msg_tx }
+    }
+}
+
+impl From<ProcessModule> for Module {
+    fn from(process: ProcessModule) -> Self {
+        let mut module = Module::new();
+
+        // exec(command, args) -> Map with the `exit_code`, `stdout` and
+        // `stderr` of the finished process
+        let tx = process.msg_tx.clone();
+        FuncRegistration::new("exec").set_into_module(
+            &mut module,
+            move |command: &str, args: rhai::Array| -> RhaiResult {
+                exec(&tx, command, args, &rhai::Map::new())
+            },
+        );
+
+        // exec(command, args, #{ cwd, env, stdin, timeout_ms })
+        let tx = process.msg_tx.clone();
+        FuncRegistration::new("exec").set_into_module(
+            &mut module,
+            move |command: &str, args: rhai::Array, options: rhai::Map| -> RhaiResult {
+                exec(&tx, command, args, &options)
+            },
+        );
+
+        module
+    }
+}
+
+/// Run `command` as a local process, blocking until it exits
+fn exec(
+    tx: &mpsc::UnboundedSender<RhaiMessage>,
+    command: &str,
+    args: rhai::Array,
+    options: &rhai::Map,
+) -> RhaiResult {
+    let (response_tx, response_rx) = std::sync::mpsc::channel();
+
+    let _ = tx.send(RhaiMessage::Exec {
+        command: command.to_string(),
+        args: string_args(args)?,
+        cwd: cwd_option(options)?,
+        env: env_option(options)?,
+        stdin: stdin_option(options)?,
+        timeout: timeout_option(options)?,
+        response_tx,
+    });
+
+    let output = response_rx
+        .recv()
+        .unwrap_or_else(|_| Err("Channel closed".to_string()))?;
+    Ok(json_to_dynamic(&output))
+}
+
+/// Read the `stdin` entry of a process's options map
+fn stdin_option(options: &rhai::Map) -> Result<Option<String>, Box<EvalAltResult>> {
+    let Some(stdin) = options.get("stdin") else {
+        return Ok(None);
+    };
+    match stdin.clone().into_string() {
+        Ok(stdin) => Ok(Some(stdin)),
+        Err(_) => Err("stdin must be a string".into()),
+    }
+}
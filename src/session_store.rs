@@ -0,0 +1,154 @@
+//! On-disk storage for session state, used to implement `session/load`
+
+use rhai::{Dynamic, Scope};
+use sacp::schema::{McpServer, SessionId, SessionUpdate};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+
+use crate::mcp_module::{dynamic_to_json, json_to_dynamic};
+
+/// Everything needed to rebuild a session after the agent restarts
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct StoredSession {
+    /// MCP servers the session was created with
+    pub mcp_servers: Vec<McpServer>,
+    /// Scope variables whose values can be represented as JSON
+    pub variables: serde_json::Map<String, serde_json::Value>,
+    /// Every update sent for the session, including the user's prompts
+    pub transcript: Vec<SessionUpdate>,
+}
+
+impl StoredSession {
+    /// Capture the variables in `scope` that can be serialized.
+    /// Values like function pointers or custom types are skipped.
+    pub fn capture_variables(&mut self, scope: &Scope<'static>) {
+        self.variables = scope
+            .iter()
+            .filter(|(_, is_constant, value)| !is_constant && is_serializable(value))
+            .map(|(name, _, value)| (name.to_string(), dynamic_to_json(&value)))
+            .collect();
+    }
+
+    /// Rebuild a Rhai scope from the stored variables
+    pub fn restore_scope(&self) -> Scope<'static> {
+        let mut scope = Scope::new();
+        for (name, value) in &self.variables {
+            scope.push_dynamic(name.clone(), json_to_dynamic(value));
+        }
+        scope
+    }
+}
+
+/// Whether a Rhai value round-trips through JSON
+fn is_serializable(value: &Dynamic) -> bool {
+    if value.is_unit() || value.is_bool() || value.is_int() || value.is_float() || value.is_string()
+    {
+        true
+    } else if value.is_array() {
+        value
+            .read_lock::<rhai::Array>()
+            .is_some_and(|arr| arr.iter().all(is_serializable))
+    } else if value.is_map() {
+        value
+            .read_lock::<rhai::Map>()
+            .is_some_and(|map| map.values().all(is_serializable))
+    } else {
+        false
+    }
+}
+
+/// Write `contents` to a new file at `path` that only the current user can
+/// read, since sessions include MCP server headers and environment variables
+/// such as access tokens
+async fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    // A leftover file would keep its old permissions, so start afresh
+    match tokio::fs::remove_file(path).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let mut options = tokio::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(path).await?;
+    file.write_all(contents).await?;
+    file.sync_all().await
+}
+
+/// A directory holding one JSON file per session
+#[derive(Clone, Debug)]
+pub struct SessionStore {
+    dir: PathBuf,
+}
+
+impl SessionStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// The default store location: `$XDG_DATA_HOME/rhaicp/sessions`,
+    /// falling back to `~/.local/share/rhaicp/sessions`
+    pub fn default_dir() -> Option<PathBuf> {
+        let data_home = std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share"))
+            })?;
+        Some(data_home.join("rhaicp").join("sessions"))
+    }
+
+    fn session_path(&self, session_id: &SessionId) -> Result<PathBuf, String> {
+        // Session ids come from the client on load, so make sure they cannot
+        // escape the store directory
+        let id = session_id.0.as_ref();
+        if id.is_empty()
+            || !id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(format!("Invalid session id '{}'", id));
+        }
+        Ok(self.dir.join(format!("{}.json", id)))
+    }
+
+    pub(crate) async fn save(
+        &self,
+        session_id: &SessionId,
+        session: &StoredSession,
+    ) -> Result<(), String> {
+        let path = self.session_path(session_id)?;
+        let json = serde_json::to_vec_pretty(session)
+            .map_err(|e| format!("Failed to serialize session: {}", e))?;
+
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .map_err(|e| format!("Failed to create {}: {}", self.dir.display(), e))?;
+
+        // Write to a temporary file first so a crash never leaves a truncated session behind
+        let tmp_path = path.with_extension("json.tmp");
+        write_private(&tmp_path, &json)
+            .await
+            .map_err(|e| format!("Failed to write {}: {}", tmp_path.display(), e))?;
+        tokio::fs::rename(&tmp_path, &path)
+            .await
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    /// Load a stored session, returning `None` if it was never saved
+    pub(crate) async fn load(
+        &self,
+        session_id: &SessionId,
+    ) -> Result<Option<StoredSession>, String> {
+        let path = self.session_path(session_id)?;
+        let json = match tokio::fs::read(&path).await {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+        serde_json::from_slice(&json)
+            .map(Some)
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))
+    }
+}
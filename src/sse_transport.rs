@@ -0,0 +1,118 @@
+//! Client side of the legacy MCP HTTP+SSE transport.
+//!
+//! The client opens a `text/event-stream` GET request. The server's first event,
+//! `endpoint`, gives the URL that client messages are POSTed to, and every
+//! server message arrives as a `message` event on the stream.
+
+use futures::StreamExt;
+use futures::stream::BoxStream;
+use rmcp::model::{ClientJsonRpcMessage, ServerJsonRpcMessage};
+use rmcp::service::RoleClient;
+use rmcp::transport::Transport;
+use sse_stream::{Sse, SseStream};
+
+type SseEvents = BoxStream<'static, Result<Sse, sse_stream::Error>>;
+
+/// MCP transport that talks to a server over HTTP+SSE
+pub(crate) struct SseClientTransport {
+    client: reqwest::Client,
+    /// Where to POST messages, as announced by the server
+    endpoint: reqwest::Url,
+    events: SseEvents,
+}
+
+impl SseClientTransport {
+    /// Open the event stream at `url` and wait for the server to announce its message endpoint
+    pub async fn connect(client: reqwest::Client, url: &str) -> Result<Self, std::io::Error> {
+        let url = reqwest::Url::parse(url).map_err(std::io::Error::other)?;
+        let response = client
+            .get(url.clone())
+            .header(reqwest::header::ACCEPT, "text/event-stream")
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(std::io::Error::other)?;
+        let mut events: SseEvents = SseStream::from_byte_stream(response.bytes_stream()).boxed();
+
+        while let Some(event) = events.next().await {
+            let event = event.map_err(std::io::Error::other)?;
+            if event.event.as_deref() != Some("endpoint") {
+                continue;
+            }
+            // The endpoint is usually relative to the event stream URL
+            let endpoint = url
+                .join(event.data.as_deref().unwrap_or_default().trim())
+                .map_err(std::io::Error::other)?;
+            // Messages may carry credentials, so they must not go anywhere
+            // but the server the event stream came from
+            if endpoint.origin() != url.origin() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!(
+                        "SSE MCP server announced an endpoint on another origin: {}",
+                        endpoint
+                    ),
+                ));
+            }
+            tracing::debug!(%endpoint, "SSE MCP server announced message endpoint");
+            return Ok(Self {
+                client,
+                endpoint,
+                events,
+            });
+        }
+
+        Err(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            "SSE stream closed before the server sent its endpoint",
+        ))
+    }
+}
+
+impl Transport<RoleClient> for SseClientTransport {
+    type Error = std::io::Error;
+
+    fn send(
+        &mut self,
+        item: ClientJsonRpcMessage,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send + 'static {
+        let request = self.client.post(self.endpoint.clone()).json(&item);
+        async move {
+            request
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(std::io::Error::other)?;
+            Ok(())
+        }
+    }
+
+    async fn receive(&mut self) -> Option<ServerJsonRpcMessage> {
+        while let Some(event) = self.events.next().await {
+            let event = match event {
+                Ok(event) => event,
+                Err(e) => {
+                    tracing::warn!(?e, "SSE MCP event stream failed");
+                    return None;
+                }
+            };
+            if !matches!(event.event.as_deref(), None | Some("message")) {
+                continue;
+            }
+            let Some(data) = event.data else {
+                continue;
+            };
+            match serde_json::from_str(&data) {
+                Ok(message) => return Some(message),
+                Err(e) => tracing::warn!(?e, ?data, "Ignoring malformed SSE MCP message"),
+            }
+        }
+        None
+    }
+
+    async fn close(&mut self) -> Result<(), Self::Error> {
+        // Dropping the response body closes the event stream
+        self.events = futures::stream::empty().boxed();
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+//! Rhai module for running commands in the client's terminals via `terminal::run`
+
+use crate::RhaiMessage;
+use crate::mcp_module::json_to_dynamic;
+use rhai::{Dynamic, EvalAltResult, FuncRegistration, Module};
+use tokio::sync::mpsc;
+
+type RhaiResult = Result<Dynamic, Box<EvalAltResult>>;
+
+/// Terminal module for Rhai that runs commands through the ACP client
+pub struct TerminalModule {
+    msg_tx: mpsc::UnboundedSender<RhaiMessage>,
+}
+
+impl TerminalModule {
+    pub fn new(msg_tx: mpsc::UnboundedSender<RhaiMessage>) -> Self {
+        Self { msg_tx }
+    }
+}
+
+impl From<TerminalModule> for Module {
+    fn from(terminal: TerminalModule) -> Self {
+        let mut module = Module::new();
+
+        // run(command, args) -> Map with the `exit_code`, `signal`, `output`
+        // and `truncated` flag of the finished command
+        let tx = terminal.msg_tx.clone();
+        FuncRegistration::new("run").set_into_module(
+            &mut module,
+            move |command: &str, args: rhai::Array| -> RhaiResult {
+                run(&tx, command, args, &rhai::Map::new())
+            },
+        );
+
+        // run(command, args, #{ cwd, env, output_byte_limit })
+        let tx = terminal.msg_tx.clone();
+        FuncRegistration::new("run").set_into_module(
+            &mut module,
+            move |command: &str, args: rhai::Array, options: rhai::Map| -> RhaiResult {
+                run(&tx, command, args, &options)
+            },
+        );
+
+        module
+    }
+}
+
+/// Run `command` in a client terminal, blocking until it exits
+fn run(
+    tx: &mpsc::UnboundedSender<RhaiMessage>,
+    command: &str,
+    args: rhai::Array,
+    options: &rhai::Map,
+) -> RhaiResult {
+    let (response_tx, response_rx) = std::sync::mpsc::channel();
+
+    let _ = tx.send(RhaiMessage::RunInTerminal {
+        command: command.to_string(),
+        args: string_args(args)?,
+        cwd: cwd_option(options)?,
+        env: env_option(options)?,
+        output_byte_limit: output_byte_limit_option(options)?,
+        response_tx,
+    });
+
+    let output = response_rx
+        .recv()
+        .unwrap_or_else(|_| Err("Channel closed".to_string()))?;
+    Ok(json_to_dynamic(&output))
+}
+
+/// The arguments of a command, which must all be strings
+pub(crate) fn string_args(args: rhai::Array) -> Result<Vec<String>, Box<EvalAltResult>> {
+    args.into_iter()
+        .map(|arg| {
+            arg.into_string()
+                .map_err(|type_name| format!("args must be strings, not {}", type_name).into())
+        })
+        .collect()
+}
+
+/// Read the `cwd` entry of a command's options map
+pub(crate) fn cwd_option(options: &rhai::Map) -> Result<Option<String>, Box<EvalAltResult>> {
+    let Some(cwd) = options.get("cwd") else {
+        return Ok(None);
+    };
+    match cwd.clone().into_string() {
+        Ok(cwd) => Ok(Some(cwd)),
+        Err(_) => Err("cwd must be a string".into()),
+    }
+}
+
+/// Read the `env` map of a command's options map as name/value pairs
+pub(crate) fn env_option(options: &rhai::Map) -> Result<Vec<(String, String)>, Box<EvalAltResult>> {
+    let Some(env) = options.get("env") else {
+        return Ok(Vec::new());
+    };
+    let Some(env) = env.read_lock::<rhai::Map>() else {
+        return Err("env must be a map".into());
+    };
+    Ok(env
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect())
+}
+
+/// Read the `output_byte_limit` entry of a command's options map
+fn output_byte_limit_option(options: &rhai::Map) -> Result<Option<u64>, Box<EvalAltResult>> {
+    let Some(limit) = options.get("output_byte_limit") else {
+        return Ok(None);
+    };
+    match limit.as_int() {
+        Ok(limit) if limit > 0 => Ok(Some(limit as u64)),
+        _ => Err("output_byte_limit must be a positive integer".into()),
+    }
+}
@@ -0,0 +1,183 @@
+//! Integration tests for asking the user questions with `confirm` and `choose`.
+//!
+//! The client in these tests answers each `session/request_permission` request
+//! with the next option from a scripted list, or cancels it once the list runs
+//! out.
+
+use rhaicp::RhaiAgent;
+use sacp::link::AgentToClient;
+use sacp::schema::{
+    InitializeRequest, ProtocolVersion, RequestPermissionOutcome, RequestPermissionRequest,
+    RequestPermissionResponse, SelectedPermissionOutcome,
+};
+use sacp::{ClientToAgent, Component};
+use sacp_conductor::{Conductor, ProxiesAndAgent};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Wrapper to make RhaiAgent work with the test infrastructure
+struct TestRhaiAgent;
+
+impl Component<AgentToClient> for TestRhaiAgent {
+    async fn serve(
+        self,
+        client: impl Component<sacp::link::ClientToAgent>,
+    ) -> Result<(), sacp::Error> {
+        Component::<AgentToClient>::serve(RhaiAgent::new(), client).await
+    }
+}
+
+fn conductor() -> impl sacp::Component<sacp::link::AgentToClient> {
+    Conductor::new_agent(
+        "test-conductor".to_string(),
+        ProxiesAndAgent::new(TestRhaiAgent),
+        Default::default(),
+    )
+}
+
+/// Run `script`, answering questions with `answers` in order. Returns the
+/// script output and each question asked, summarized with its options.
+async fn prompt_with_answers(
+    script: &str,
+    answers: &[&str],
+) -> Result<(String, Vec<String>), sacp::Error> {
+    let answers = Arc::new(Mutex::new(
+        answers
+            .iter()
+            .map(|answer| answer.to_string())
+            .collect::<VecDeque<_>>(),
+    ));
+    let asked = Arc::new(Mutex::new(Vec::new()));
+
+    let output = ClientToAgent::builder()
+        .on_receive_request(
+            {
+                let answers = answers.clone();
+                let asked = asked.clone();
+                async move |request: RequestPermissionRequest, request_cx, _cx| {
+                    let options: Vec<_> = request
+                        .options
+                        .iter()
+                        .map(|option| {
+                            format!("{}={} {:?}", option.option_id.0, option.name, option.kind)
+                        })
+                        .collect();
+                    asked.lock().unwrap().push(format!(
+                        "{} {:?}",
+                        request.tool_call.fields.title.unwrap_or_default(),
+                        options
+                    ));
+                    let outcome = match answers.lock().unwrap().pop_front() {
+                        Some(answer) => RequestPermissionOutcome::Selected(
+                            SelectedPermissionOutcome::new(answer),
+                        ),
+                        None => RequestPermissionOutcome::Cancelled,
+                    };
+                    request_cx.respond(RequestPermissionResponse::new(outcome))
+                }
+            },
+            sacp::on_receive_request!(),
+        )
+        .connect_to(conductor())?
+        .run_until(async |cx| {
+            cx.send_request(InitializeRequest::new(ProtocolVersion::LATEST))
+                .block_task()
+                .await?;
+
+            let mut session = cx
+                .build_session(PathBuf::from("."))
+                .block_task()
+                .start_session()
+                .await?;
+
+            session.send_prompt(script)?;
+            session.read_to_string().await
+        })
+        .await?;
+
+    let asked = asked.lock().unwrap().clone();
+    Ok((output, asked))
+}
+
+#[tokio::test]
+async fn test_confirm_returns_whether_the_user_said_yes() -> Result<(), sacp::Error> {
+    let (output, asked) = prompt_with_answers(
+        r#"
+        say(confirm("Deploy?") + " ");
+        say(`${confirm("Deploy again?")}`);
+        "#,
+        &["yes", "no"],
+    )
+    .await?;
+
+    assert_eq!(output, "true false");
+    expect_test::expect![[r#"
+        [
+            "Deploy? [\"yes=Yes AllowOnce\", \"no=No RejectOnce\"]",
+            "Deploy again? [\"yes=Yes AllowOnce\", \"no=No RejectOnce\"]",
+        ]
+    "#]]
+    .assert_debug_eq(&asked);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_choose_returns_the_selected_option() -> Result<(), sacp::Error> {
+    let (output, asked) = prompt_with_answers(
+        r#"
+        say(choose("Which branch?", ["main", "develop"]) + " ");
+        say(choose("Clean up?", [
+            #{ id: "keep", name: "Keep the files" },
+            #{ id: "delete", name: "Delete the files", kind: "reject_once" },
+        ]));
+        "#,
+        &["develop", "delete"],
+    )
+    .await?;
+
+    assert_eq!(output, "develop delete");
+    expect_test::expect![[r#"
+        [
+            "Which branch? [\"main=main AllowOnce\", \"develop=develop AllowOnce\"]",
+            "Clean up? [\"keep=Keep the files AllowOnce\", \"delete=Delete the files RejectOnce\"]",
+        ]
+    "#]]
+    .assert_debug_eq(&asked);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cancelled_and_invalid_questions_throw() -> Result<(), sacp::Error> {
+    let (output, asked) = prompt_with_answers(
+        r#"
+        try {
+            confirm("Continue?");
+        } catch (err) {
+            say(err + "\n");
+        }
+        try {
+            choose("Pick one", []);
+        } catch (err) {
+            say(err + "\n");
+        }
+        try {
+            choose("Pick one", [#{ id: "a", name: "A", kind: "maybe" }]);
+        } catch (err) {
+            say(err + "\n");
+        }
+        "#,
+        &[],
+    )
+    .await?;
+
+    expect_test::expect![[r#"
+        "The question was cancelled\nchoose() needs at least one option\nUnknown option kind: maybe\n"
+    "#]]
+    .assert_debug_eq(&output);
+    assert_eq!(asked.len(), 1);
+
+    Ok(())
+}
@@ -0,0 +1,173 @@
+//! Integration tests for `session/cancel` handling.
+
+mod common;
+
+use rhaicp::RhaiAgent;
+use rmcp::model::{
+    CallToolRequestParam, CallToolResult, ListToolsResult, PaginatedRequestParam,
+    ServerCapabilities, ServerInfo, Tool,
+};
+use rmcp::service::{RequestContext, RoleServer};
+use rmcp::{ErrorData as McpError, ServerHandler};
+use sacp::link::AgentToClient;
+use sacp::schema::{
+    CancelNotification, InitializeRequest, McpServer, McpServerHttp, NewSessionRequest,
+    ProtocolVersion, StopReason,
+};
+use sacp::{ClientToAgent, Component, SessionMessage};
+use sacp_conductor::{Conductor, ProxiesAndAgent};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+/// Wrapper to make RhaiAgent work with the test infrastructure
+struct TestRhaiAgent;
+
+impl Component<AgentToClient> for TestRhaiAgent {
+    async fn serve(
+        self,
+        client: impl Component<sacp::link::ClientToAgent>,
+    ) -> Result<(), sacp::Error> {
+        Component::<AgentToClient>::serve(RhaiAgent::new(), client).await
+    }
+}
+
+fn conductor() -> impl sacp::Component<sacp::link::AgentToClient> {
+    Conductor::new_agent(
+        "test-conductor".to_string(),
+        ProxiesAndAgent::new(TestRhaiAgent),
+        Default::default(),
+    )
+}
+
+#[tokio::test]
+async fn test_cancel_aborts_infinite_loop() -> Result<(), sacp::Error> {
+    let (stop_reason, after_cancel) = ClientToAgent::builder()
+        .connect_to(conductor())?
+        .run_until(async |cx| {
+            cx.send_request(InitializeRequest::new(ProtocolVersion::LATEST))
+                .block_task()
+                .await?;
+
+            let mut session = cx
+                .build_session(PathBuf::from("."))
+                .block_task()
+                .start_session()
+                .await?;
+
+            // Wait until the script is running before cancelling it
+            session.send_prompt(r#"say("started"); loop { }"#)?;
+            session.read_update().await?;
+            cx.send_notification(CancelNotification::new(session.session_id().clone()))?;
+
+            let stop_reason = loop {
+                if let SessionMessage::StopReason(stop_reason) = session.read_update().await? {
+                    break stop_reason;
+                }
+            };
+
+            // The session remains usable after a cancelled prompt
+            session.send_prompt(r#"say("still alive")"#)?;
+            let after_cancel = session.read_to_string().await?;
+
+            Ok((stop_reason, after_cancel))
+        })
+        .await?;
+
+    assert_eq!(stop_reason, StopReason::Cancelled);
+    expect_test::expect![[r#"
+        "still alive"
+    "#]]
+    .assert_debug_eq(&after_cancel);
+
+    Ok(())
+}
+
+/// MCP server with a `nap` tool that says when it starts, then sleeps for
+/// longer than any test runs
+struct SleepyServer {
+    started: Arc<Notify>,
+}
+
+impl ServerHandler for SleepyServer {
+    fn get_info(&self) -> ServerInfo {
+        ServerInfo {
+            capabilities: ServerCapabilities::builder().enable_tools().build(),
+            ..Default::default()
+        }
+    }
+
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        Ok(ListToolsResult::with_all_items(vec![Tool::new(
+            "nap",
+            "Sleeps for a minute",
+            Arc::new(serde_json::Map::new()),
+        )]))
+    }
+
+    async fn call_tool(
+        &self,
+        _request: CallToolRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        self.started.notify_one();
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        Ok(CallToolResult::success(vec![]))
+    }
+}
+
+#[tokio::test]
+async fn test_cancel_drops_in_flight_mcp_request() -> Result<(), sacp::Error> {
+    let started = Arc::new(Notify::new());
+    let url = common::serve_http({
+        let started = started.clone();
+        move || {
+            Ok(SleepyServer {
+                started: started.clone(),
+            })
+        }
+    })
+    .await;
+
+    let (stop_reason, waited) = ClientToAgent::builder()
+        .connect_to(conductor())?
+        .run_until(async |cx| {
+            cx.send_request(InitializeRequest::new(ProtocolVersion::LATEST))
+                .block_task()
+                .await?;
+
+            let mut session = cx
+                .build_session_from(
+                    NewSessionRequest::new(".")
+                        .mcp_servers(vec![McpServer::Http(McpServerHttp::new("sleepy", url))]),
+                )
+                .block_task()
+                .start_session()
+                .await?;
+
+            // Cancel once the tool call has reached the server
+            session.send_prompt(r#"mcp::call_tool("sleepy", "nap", #{});"#)?;
+            started.notified().await;
+            let cancelled_at = Instant::now();
+            cx.send_notification(CancelNotification::new(session.session_id().clone()))?;
+
+            let stop_reason = loop {
+                if let SessionMessage::StopReason(stop_reason) = session.read_update().await? {
+                    break stop_reason;
+                }
+            };
+
+            Ok((stop_reason, cancelled_at.elapsed()))
+        })
+        .await?;
+
+    assert_eq!(stop_reason, StopReason::Cancelled);
+    assert!(waited < Duration::from_secs(5), "waited {:?}", waited);
+
+    Ok(())
+}
@@ -0,0 +1,148 @@
+//! Integration tests for the capabilities rhaicp advertises and enforces.
+
+use rhaicp::RhaiAgent;
+use sacp::link::AgentToClient;
+use sacp::schema::{
+    AudioContent, BlobResourceContents, ContentBlock, ContentChunk, EmbeddedResource,
+    EmbeddedResourceResource, ImageContent, InitializeRequest, NewSessionRequest, PromptRequest,
+    ProtocolVersion, SessionNotification, SessionUpdate, TextContent, TextResourceContents,
+};
+use sacp::{ClientToAgent, Component};
+use sacp_conductor::{Conductor, ProxiesAndAgent};
+use std::sync::{Arc, Mutex};
+
+/// Wrapper to make RhaiAgent work with the test infrastructure
+struct TestRhaiAgent;
+
+impl Component<AgentToClient> for TestRhaiAgent {
+    async fn serve(
+        self,
+        client: impl Component<sacp::link::ClientToAgent>,
+    ) -> Result<(), sacp::Error> {
+        Component::<AgentToClient>::serve(RhaiAgent::new(), client).await
+    }
+}
+
+fn conductor() -> impl sacp::Component<sacp::link::AgentToClient> {
+    Conductor::new_agent(
+        "test-conductor".to_string(),
+        ProxiesAndAgent::new(TestRhaiAgent),
+        Default::default(),
+    )
+}
+
+#[tokio::test]
+async fn test_initialize_advertises_capabilities() -> Result<(), sacp::Error> {
+    let response = ClientToAgent::builder()
+        .connect_to(conductor())?
+        .run_until(async |cx| {
+            cx.send_request(InitializeRequest::new(ProtocolVersion::LATEST))
+                .block_task()
+                .await
+        })
+        .await?;
+
+    let capabilities = response.agent_capabilities;
+    assert!(capabilities.load_session);
+    assert!(capabilities.mcp_capabilities.http);
+    assert!(capabilities.mcp_capabilities.sse);
+    assert!(capabilities.prompt_capabilities.embedded_context);
+    assert!(capabilities.prompt_capabilities.image);
+    assert!(capabilities.prompt_capabilities.audio);
+
+    Ok(())
+}
+
+/// Send a prompt made of `blocks` and return the text the script said
+async fn prompt_blocks(blocks: Vec<ContentBlock>) -> Result<String, sacp::Error> {
+    let output = Arc::new(Mutex::new(String::new()));
+    ClientToAgent::builder()
+        .on_receive_notification(
+            {
+                let output = output.clone();
+                async move |notification: SessionNotification, _cx| {
+                    if let SessionUpdate::AgentMessageChunk(ContentChunk {
+                        content: ContentBlock::Text(TextContent { text, .. }),
+                        ..
+                    }) = notification.update
+                    {
+                        output.lock().unwrap().push_str(&text);
+                    }
+                    Ok(())
+                }
+            },
+            sacp::on_receive_notification!(),
+        )
+        .connect_to(conductor())?
+        .run_until(async |cx| {
+            cx.send_request(InitializeRequest::new(ProtocolVersion::LATEST))
+                .block_task()
+                .await?;
+
+            let session = cx
+                .send_request(NewSessionRequest::new("."))
+                .block_task()
+                .await?;
+
+            cx.send_request(PromptRequest::new(session.session_id, blocks))
+                .block_task()
+                .await?;
+
+            Ok(())
+        })
+        .await?;
+
+    let output = std::mem::take(&mut *output.lock().unwrap());
+    Ok(output)
+}
+
+#[tokio::test]
+async fn test_embedded_script_is_executed() -> Result<(), sacp::Error> {
+    let script = ContentBlock::Resource(EmbeddedResource::new(
+        EmbeddedResourceResource::TextResourceContents(TextResourceContents::new(
+            r#"say("from an attached file")"#,
+            "file:///script.rhai",
+        )),
+    ));
+    let output = prompt_blocks(vec![script]).await?;
+
+    expect_test::expect![[r#"
+        "from an attached file"
+    "#]]
+    .assert_debug_eq(&output);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_images_audio_and_binary_resources_are_attachments() -> Result<(), sacp::Error> {
+    let output = prompt_blocks(vec![
+        ContentBlock::Text(TextContent::new(
+            r#"
+            for attachment in ATTACHMENTS {
+                if attachment.type == "resource" {
+                    say("resource " + attachment.uri + " " + attachment.blob + "\n");
+                } else {
+                    say(attachment.type + " " + attachment.mime_type + " " + attachment.data + "\n");
+                }
+            }
+            "#,
+        )),
+        ContentBlock::Image(ImageContent::new("aW1hZ2U=", "image/png")),
+        ContentBlock::Audio(AudioContent::new("YXVkaW8=", "audio/wav")),
+        ContentBlock::Resource(EmbeddedResource::new(
+            EmbeddedResourceResource::BlobResourceContents(BlobResourceContents::new(
+                "AAAA",
+                "file:///data.bin",
+            )),
+        )),
+    ])
+    .await?;
+
+    expect_test::expect![[r#"
+        "image image/png aW1hZ2U=\naudio audio/wav YXVkaW8=\nresource file:///data.bin AAAA\n"
+    "#]]
+    .assert_debug_eq(&output);
+
+    Ok(())
+}
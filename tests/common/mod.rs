@@ -0,0 +1,86 @@
+//! Helpers for integration tests that run an in-process streamable HTTP MCP
+//! server. Each test crate uses only some of them.
+
+#![allow(dead_code)]
+
+use rmcp::ServerHandler;
+use rmcp::transport::streamable_http_server::StreamableHttpService;
+use rmcp::transport::streamable_http_server::session::local::LocalSessionManager;
+use sacp::link::AgentToClient;
+use sacp::schema::{
+    InitializeRequest, McpServer, McpServerHttp, NewSessionRequest, ProtocolVersion,
+};
+use sacp::{ClientToAgent, Component, JrConnectionBuilder, JrMessageHandler};
+use std::sync::Arc;
+
+/// A router serving the MCP servers that `make_server` creates, one per
+/// connection, at `/mcp`
+pub fn mcp_router<S: ServerHandler>(
+    make_server: impl Fn() -> Result<S, std::io::Error> + Send + Sync + 'static,
+) -> axum::Router {
+    let service = StreamableHttpService::new(
+        make_server,
+        Arc::new(LocalSessionManager::default()),
+        Default::default(),
+    );
+    axum::Router::new().nest_service("/mcp", service)
+}
+
+/// Serve `router` on a free local port, returning the URL of its `/mcp` route
+pub async fn serve_router(router: axum::Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/mcp", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, router).await });
+
+    url
+}
+
+/// Serve the MCP servers that `make_server` creates over HTTP, returning
+/// their URL
+pub async fn serve_http<S: ServerHandler>(
+    make_server: impl Fn() -> Result<S, std::io::Error> + Send + Sync + 'static,
+) -> String {
+    serve_router(mcp_router(make_server)).await
+}
+
+/// Run `script` against `agent` in a session whose only MCP server is
+/// `server`, returning the script output
+pub async fn prompt_with_server(
+    agent: impl Component<AgentToClient> + 'static,
+    server: McpServerHttp,
+    script: &str,
+) -> Result<String, sacp::Error> {
+    prompt_with_server_using(ClientToAgent::builder(), agent, server, script).await
+}
+
+/// Like [`prompt_with_server`], with `client` handling the requests and
+/// notifications the agent sends
+pub async fn prompt_with_server_using<H>(
+    client: JrConnectionBuilder<H>,
+    agent: impl Component<AgentToClient> + 'static,
+    server: McpServerHttp,
+    script: &str,
+) -> Result<String, sacp::Error>
+where
+    H: JrMessageHandler<Link = ClientToAgent> + 'static,
+{
+    client
+        .connect_to(agent)?
+        .run_until(async |cx| {
+            cx.send_request(InitializeRequest::new(ProtocolVersion::LATEST))
+                .block_task()
+                .await?;
+
+            let mut session = cx
+                .build_session_from(
+                    NewSessionRequest::new(".").mcp_servers(vec![McpServer::Http(server)]),
+                )
+                .block_task()
+                .start_session()
+                .await?;
+
+            session.send_prompt(script)?;
+            session.read_to_string().await
+        })
+        .await
+}
@@ -0,0 +1,197 @@
+//! Integration tests for restricting script file access to the allowed roots.
+
+use rhaicp::{FsPolicy, RhaiAgent};
+use sacp::link::AgentToClient;
+use sacp::schema::{
+    ContentBlock, ContentChunk, InitializeRequest, ProtocolVersion, SessionNotification,
+    SessionUpdate, ToolCallStatus,
+};
+use sacp::{ClientToAgent, Component};
+use sacp_conductor::{Conductor, ProxiesAndAgent};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Wrapper to make RhaiAgent work with the test infrastructure
+struct TestRhaiAgent(FsPolicy);
+
+impl Component<AgentToClient> for TestRhaiAgent {
+    async fn serve(
+        self,
+        client: impl Component<sacp::link::ClientToAgent>,
+    ) -> Result<(), sacp::Error> {
+        Component::<AgentToClient>::serve(RhaiAgent::new().with_fs_policy(self.0), client).await
+    }
+}
+
+fn conductor(policy: FsPolicy) -> impl sacp::Component<sacp::link::AgentToClient> {
+    Conductor::new_agent(
+        "test-conductor".to_string(),
+        ProxiesAndAgent::new(TestRhaiAgent(policy)),
+        Default::default(),
+    )
+}
+
+/// A fresh temporary directory containing an empty `project` directory to use
+/// as the session cwd
+fn temp_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rhaicp-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(dir.join("project")).unwrap();
+    dir
+}
+
+/// Run `script` in a session whose cwd is `cwd`, returning its output and the
+/// final status of each tool call
+async fn prompt_in(
+    policy: FsPolicy,
+    cwd: &Path,
+    script: &str,
+) -> Result<(String, Vec<ToolCallStatus>), sacp::Error> {
+    let updates = Arc::new(Mutex::new(Vec::new()));
+    ClientToAgent::builder()
+        .on_receive_notification(
+            {
+                let updates = updates.clone();
+                async move |notification: SessionNotification, _cx| {
+                    updates.lock().unwrap().push(notification.update);
+                    Ok(())
+                }
+            },
+            sacp::on_receive_notification!(),
+        )
+        .connect_to(conductor(policy))?
+        .run_until(async |cx| {
+            cx.send_request(InitializeRequest::new(ProtocolVersion::LATEST))
+                .block_task()
+                .await?;
+
+            let mut session = cx.build_session(cwd).block_task().start_session().await?;
+
+            session.send_prompt(script)?;
+            session.read_to_string().await
+        })
+        .await?;
+
+    let mut output = String::new();
+    let mut statuses = Vec::new();
+    for update in updates.lock().unwrap().iter() {
+        match update {
+            SessionUpdate::AgentMessageChunk(ContentChunk {
+                content: ContentBlock::Text(text),
+                ..
+            }) => output.push_str(&text.text),
+            SessionUpdate::ToolCallUpdate(update) => {
+                statuses.extend(update.fields.status.filter(|status| {
+                    matches!(status, ToolCallStatus::Completed | ToolCallStatus::Failed)
+                }))
+            }
+            _ => {}
+        }
+    }
+    Ok((output, statuses))
+}
+
+#[tokio::test]
+async fn test_access_outside_cwd_is_denied() -> Result<(), sacp::Error> {
+    let dir = temp_dir();
+    std::fs::write(dir.join("secret.txt"), "secret").unwrap();
+    std::fs::create_dir(dir.join("project/src")).unwrap();
+
+    let (output, statuses) = prompt_in(
+        FsPolicy::new(),
+        &dir.join("project"),
+        &format!(
+            r#"
+            for attempt in [
+                || read_file("../secret.txt"),
+                || read_file("{secret}"),
+                || write_file("../escape.txt", "escaped"),
+                || write_file("{dir}/escape.txt", "escaped"),
+            ] {{
+                try {{
+                    attempt.call();
+                    say("allowed\n");
+                }} catch (err) {{
+                    say(err.starts_with("Access denied to ") + "\n");
+                }}
+            }}
+            write_file("notes.txt", "kept");
+            write_file("src/../notes.txt", "still inside");
+            say(read_file("notes.txt"));
+            "#,
+            secret = dir.join("secret.txt").display(),
+            dir = dir.display(),
+        ),
+    )
+    .await?;
+
+    assert_eq!(output, "true\ntrue\ntrue\ntrue\nstill inside");
+    assert_eq!(
+        statuses,
+        vec![
+            ToolCallStatus::Failed,
+            ToolCallStatus::Failed,
+            ToolCallStatus::Failed,
+            ToolCallStatus::Failed,
+            ToolCallStatus::Completed,
+            ToolCallStatus::Completed,
+            ToolCallStatus::Completed,
+        ]
+    );
+    assert!(!dir.join("escape.txt").exists());
+
+    let _ = std::fs::remove_dir_all(&dir);
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_symlinks_cannot_escape_cwd() -> Result<(), sacp::Error> {
+    let dir = temp_dir();
+    std::fs::create_dir(dir.join("outside")).unwrap();
+    std::os::unix::fs::symlink(dir.join("outside"), dir.join("project/link")).unwrap();
+    std::os::unix::fs::symlink(dir.join("missing.txt"), dir.join("project/dangling.txt")).unwrap();
+
+    let (output, _) = prompt_in(
+        FsPolicy::new(),
+        &dir.join("project"),
+        r#"
+        for path in ["link/escape.txt", "dangling.txt"] {
+            try {
+                write_file(path, "escaped");
+                say("allowed\n");
+            } catch (err) {
+                say(err.starts_with("Access denied to ") + "\n");
+            }
+        }
+        "#,
+    )
+    .await?;
+
+    assert_eq!(output, "true\ntrue\n");
+    assert!(!dir.join("outside/escape.txt").exists());
+    assert!(!dir.join("missing.txt").exists());
+
+    let _ = std::fs::remove_dir_all(&dir);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_extra_roots_are_allowed() -> Result<(), sacp::Error> {
+    let dir = temp_dir();
+    std::fs::create_dir(dir.join("shared")).unwrap();
+
+    let (output, _) = prompt_in(
+        FsPolicy::new().allow_root(dir.join("shared")),
+        &dir.join("project"),
+        r#"
+        write_file("../shared/notes.txt", "shared");
+        say(read_file("../shared/notes.txt"));
+        "#,
+    )
+    .await?;
+
+    assert_eq!(output, "shared");
+
+    let _ = std::fs::remove_dir_all(&dir);
+    Ok(())
+}
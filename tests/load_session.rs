@@ -0,0 +1,157 @@
+//! Integration tests for restoring saved sessions with `session/load`.
+
+use rhaicp::{RhaiAgent, SessionStore};
+use sacp::link::AgentToClient;
+use sacp::schema::{
+    ContentBlock, InitializeRequest, LoadSessionRequest, PromptRequest, ProtocolVersion, SessionId,
+    SessionNotification, SessionUpdate, TextContent,
+};
+use sacp::{ClientToAgent, Component};
+use sacp_conductor::{Conductor, ProxiesAndAgent};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Wrapper to make RhaiAgent work with the test infrastructure
+struct TestRhaiAgent {
+    session_dir: PathBuf,
+}
+
+impl Component<AgentToClient> for TestRhaiAgent {
+    async fn serve(
+        self,
+        client: impl Component<sacp::link::ClientToAgent>,
+    ) -> Result<(), sacp::Error> {
+        let agent = RhaiAgent::new().with_session_store(SessionStore::new(self.session_dir));
+        Component::<AgentToClient>::serve(agent, client).await
+    }
+}
+
+fn conductor(session_dir: &Path) -> impl sacp::Component<sacp::link::AgentToClient> {
+    Conductor::new_agent(
+        "test-conductor".to_string(),
+        ProxiesAndAgent::new(TestRhaiAgent {
+            session_dir: session_dir.to_path_buf(),
+        }),
+        Default::default(),
+    )
+}
+
+/// Summarize an update as `kind: text` for comparison
+fn describe_update(update: &SessionUpdate) -> String {
+    let (kind, content) = match update {
+        SessionUpdate::UserMessageChunk(chunk) => ("user", &chunk.content),
+        SessionUpdate::AgentMessageChunk(chunk) => ("agent", &chunk.content),
+        _ => return format!("{:?}", update),
+    };
+    match content {
+        ContentBlock::Text(TextContent { text, .. }) => format!("{}: {}", kind, text),
+        _ => format!("{}: {:?}", kind, content),
+    }
+}
+
+#[tokio::test]
+async fn test_load_session_restores_state() -> Result<(), sacp::Error> {
+    let session_dir = std::env::temp_dir().join(format!("rhaicp-test-{}", uuid::Uuid::new_v4()));
+
+    // First connection: create a session and define some state
+    let session_id: SessionId = ClientToAgent::builder()
+        .connect_to(conductor(&session_dir))?
+        .run_until(async |cx| {
+            cx.send_request(InitializeRequest::new(ProtocolVersion::LATEST))
+                .block_task()
+                .await?;
+
+            let mut session = cx
+                .build_session(PathBuf::from("."))
+                .block_task()
+                .start_session()
+                .await?;
+
+            session.send_prompt(r#"let answer = 41; say("defined");"#)?;
+            session.read_to_string().await?;
+
+            Ok(session.session_id().clone())
+        })
+        .await?;
+
+    // Saved sessions can hold MCP credentials, so only the user may read them
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let path = session_dir.join(format!("{}.json", session_id.0));
+        let mode = std::fs::metadata(path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    // Second connection: a fresh agent loads the session from disk
+    let updates = Arc::new(Mutex::new(Vec::new()));
+    ClientToAgent::builder()
+        .on_receive_notification(
+            {
+                let updates = updates.clone();
+                async move |notification: SessionNotification, _cx| {
+                    updates
+                        .lock()
+                        .unwrap()
+                        .push(describe_update(&notification.update));
+                    Ok(())
+                }
+            },
+            sacp::on_receive_notification!(),
+        )
+        .connect_to(conductor(&session_dir))?
+        .run_until(async |cx| {
+            cx.send_request(InitializeRequest::new(ProtocolVersion::LATEST))
+                .block_task()
+                .await?;
+
+            cx.send_request(LoadSessionRequest::new(session_id.clone(), "."))
+                .block_task()
+                .await?;
+
+            cx.send_request(PromptRequest::new(
+                session_id.clone(),
+                vec!["answer += 1; say(answer.to_string());".into()],
+            ))
+            .block_task()
+            .await?;
+
+            Ok(())
+        })
+        .await?;
+
+    expect_test::expect![[r#"
+        [
+            "user: let answer = 41; say(\"defined\");",
+            "agent: defined",
+            "agent: 42",
+        ]
+    "#]]
+    .assert_debug_eq(&updates.lock().unwrap());
+
+    let _ = std::fs::remove_dir_all(&session_dir);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_load_unknown_session_fails() -> Result<(), sacp::Error> {
+    let session_dir = std::env::temp_dir().join(format!("rhaicp-test-{}", uuid::Uuid::new_v4()));
+
+    let result = ClientToAgent::builder()
+        .connect_to(conductor(&session_dir))?
+        .run_until(async |cx| {
+            cx.send_request(InitializeRequest::new(ProtocolVersion::LATEST))
+                .block_task()
+                .await?;
+
+            Ok(cx
+                .send_request(LoadSessionRequest::new("no-such-session", "."))
+                .block_task()
+                .await)
+        })
+        .await?;
+
+    assert!(result.is_err(), "Expected an error, got: {:?}", result);
+
+    Ok(())
+}
@@ -0,0 +1,132 @@
+//! Integration tests for `mcp::call_tool_result`.
+//!
+//! These tests run an in-process streamable HTTP MCP server whose tools return
+//! several kinds of content blocks and tool-reported errors.
+
+mod common;
+
+use rhaicp::RhaiAgent;
+use rmcp::model::{
+    CallToolRequestParam, CallToolResult, Content, ListToolsResult, PaginatedRequestParam,
+    ServerCapabilities, ServerInfo, Tool,
+};
+use rmcp::service::{RequestContext, RoleServer};
+use rmcp::{ErrorData as McpError, ServerHandler};
+use sacp::Component;
+use sacp::link::AgentToClient;
+use sacp::schema::McpServerHttp;
+use sacp_conductor::{Conductor, ProxiesAndAgent};
+use std::sync::Arc;
+
+/// Wrapper to make RhaiAgent work with the test infrastructure
+struct TestRhaiAgent;
+
+impl Component<AgentToClient> for TestRhaiAgent {
+    async fn serve(
+        self,
+        client: impl Component<sacp::link::ClientToAgent>,
+    ) -> Result<(), sacp::Error> {
+        Component::<AgentToClient>::serve(RhaiAgent::new(), client).await
+    }
+}
+
+fn conductor() -> impl sacp::Component<sacp::link::AgentToClient> {
+    Conductor::new_agent(
+        "test-conductor".to_string(),
+        ProxiesAndAgent::new(TestRhaiAgent),
+        Default::default(),
+    )
+}
+
+/// MCP server with a `report` tool returning mixed content and a `fail` tool
+/// that reports an error
+struct ReportServer;
+
+impl ServerHandler for ReportServer {
+    fn get_info(&self) -> ServerInfo {
+        ServerInfo {
+            capabilities: ServerCapabilities::builder().enable_tools().build(),
+            ..Default::default()
+        }
+    }
+
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        let schema = Arc::new(serde_json::Map::new());
+        Ok(ListToolsResult::with_all_items(vec![
+            Tool::new("report", "Return a report", schema.clone()),
+            Tool::new("fail", "Always fails", schema),
+        ]))
+    }
+
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        match request.name.as_ref() {
+            "report" => Ok(CallToolResult::success(vec![
+                Content::text("Summary"),
+                Content::text("Details"),
+                Content::image("aGVsbG8=", "image/png"),
+                Content::embedded_text("file:///report.txt", "Full report"),
+            ])),
+            _ => Ok(CallToolResult::error(vec![Content::text(
+                "Something broke",
+            )])),
+        }
+    }
+}
+
+/// Run `script` in a session whose only MCP server is the report server
+async fn prompt_with_reports(script: &str) -> Result<String, sacp::Error> {
+    let url = common::serve_http(|| Ok(ReportServer)).await;
+    common::prompt_with_server(conductor(), McpServerHttp::new("reports", url), script).await
+}
+
+#[tokio::test]
+async fn test_all_content_blocks_are_returned() -> Result<(), sacp::Error> {
+    let result = prompt_with_reports(
+        r#"
+        let result = mcp::call_tool_result("reports", "report", #{});
+        say("is_error: " + result.is_error + "\n");
+        say("value: " + result.value + "\n");
+        for block in result.content {
+            switch block.type {
+                "text" => say("text: " + block.text + "\n"),
+                "image" => say("image: " + block.mime_type + "\n"),
+                "resource" => say("resource: " + block.uri + " " + block.text + "\n"),
+            }
+        }
+        "#,
+    )
+    .await?;
+
+    expect_test::expect![[r#"
+        "is_error: false\nvalue: Summary\ntext: Summary\ntext: Details\nimage: image/png\nresource: file:///report.txt Full report\n"
+    "#]]
+    .assert_debug_eq(&result);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_error_flag_is_returned() -> Result<(), sacp::Error> {
+    let result = prompt_with_reports(
+        r#"
+        let result = mcp::call_tool_result("reports", "fail", #{});
+        say("is_error: " + result.is_error + ", message: " + result.content[0].text);
+        "#,
+    )
+    .await?;
+
+    expect_test::expect![[r#"
+        "is_error: true, message: Something broke"
+    "#]]
+    .assert_debug_eq(&result);
+
+    Ok(())
+}
@@ -0,0 +1,126 @@
+//! Integration tests for `mcp::describe_tools`.
+//!
+//! These tests run an in-process streamable HTTP MCP server whose tool has a
+//! title, input and output schemas, and annotations.
+
+mod common;
+
+use rhaicp::RhaiAgent;
+use rmcp::model::{
+    ListToolsResult, PaginatedRequestParam, ServerCapabilities, ServerInfo, Tool, ToolAnnotations,
+};
+use rmcp::service::{RequestContext, RoleServer};
+use rmcp::{ErrorData as McpError, ServerHandler};
+use sacp::Component;
+use sacp::link::AgentToClient;
+use sacp::schema::McpServerHttp;
+use sacp_conductor::{Conductor, ProxiesAndAgent};
+use std::sync::Arc;
+
+/// Wrapper to make RhaiAgent work with the test infrastructure
+struct TestRhaiAgent;
+
+impl Component<AgentToClient> for TestRhaiAgent {
+    async fn serve(
+        self,
+        client: impl Component<sacp::link::ClientToAgent>,
+    ) -> Result<(), sacp::Error> {
+        Component::<AgentToClient>::serve(RhaiAgent::new(), client).await
+    }
+}
+
+fn conductor() -> impl sacp::Component<sacp::link::AgentToClient> {
+    Conductor::new_agent(
+        "test-conductor".to_string(),
+        ProxiesAndAgent::new(TestRhaiAgent),
+        Default::default(),
+    )
+}
+
+fn json_object(value: serde_json::Value) -> Arc<serde_json::Map<String, serde_json::Value>> {
+    match value {
+        serde_json::Value::Object(object) => Arc::new(object),
+        _ => panic!("expected a JSON object"),
+    }
+}
+
+/// MCP server with a single, fully described `get_user` tool
+struct UsersServer;
+
+impl ServerHandler for UsersServer {
+    fn get_info(&self) -> ServerInfo {
+        ServerInfo {
+            capabilities: ServerCapabilities::builder().enable_tools().build(),
+            ..Default::default()
+        }
+    }
+
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        let tool = Tool {
+            title: Some("Get User".to_string()),
+            output_schema: Some(json_object(serde_json::json!({
+                "type": "object",
+                "properties": { "name": { "type": "string" } },
+            }))),
+            ..Tool::new(
+                "get_user",
+                "Get user info by ID",
+                json_object(serde_json::json!({
+                    "type": "object",
+                    "properties": { "id": { "type": "integer" } },
+                    "required": ["id"],
+                })),
+            )
+        }
+        .annotate(ToolAnnotations::new().read_only(true));
+        Ok(ListToolsResult::with_all_items(vec![tool]))
+    }
+}
+
+/// Run `script` in a session whose only MCP server is the users server
+async fn prompt_with_users(script: &str) -> Result<String, sacp::Error> {
+    let url = common::serve_http(|| Ok(UsersServer)).await;
+    common::prompt_with_server(conductor(), McpServerHttp::new("users", url), script).await
+}
+
+#[tokio::test]
+async fn test_describe_tools() -> Result<(), sacp::Error> {
+    let result = prompt_with_users(
+        r#"
+        for tool in mcp::describe_tools("users") {
+            say("name: " + tool.name + "\n");
+            say("title: " + tool.title + "\n");
+            say("description: " + tool.description + "\n");
+            say("required: " + tool.input_schema.required.to_string() + "\n");
+            say("id type: " + tool.input_schema.properties.id["type"] + "\n");
+            say("output: " + tool.output_schema.properties.keys().to_string() + "\n");
+            say("read only: " + tool.annotations.read_only_hint + "\n");
+            say("destructive: " + type_of(tool.annotations.destructive_hint) + "\n");
+        }
+        "#,
+    )
+    .await?;
+
+    expect_test::expect![[r#"
+        "name: get_user\ntitle: Get User\ndescription: Get user info by ID\nrequired: [\"id\"]\nid type: integer\noutput: [\"name\"]\nread only: true\ndestructive: ()\n"
+    "#]]
+    .assert_debug_eq(&result);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_list_tools_still_returns_names() -> Result<(), sacp::Error> {
+    let result = prompt_with_users(r#"say(mcp::list_tools("users").to_string());"#).await?;
+
+    expect_test::expect![[r#"
+        "[\"get_user\"]"
+    "#]]
+    .assert_debug_eq(&result);
+
+    Ok(())
+}
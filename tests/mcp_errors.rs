@@ -0,0 +1,193 @@
+//! Integration tests for the errors `mcp::*` functions throw to scripts.
+//!
+//! These tests run an in-process streamable HTTP MCP server with tools that
+//! succeed, report errors in their results, or are missing entirely.
+
+mod common;
+
+use rhaicp::RhaiAgent;
+use rmcp::model::{
+    CallToolRequestParam, CallToolResult, Content, ListToolsResult, PaginatedRequestParam,
+    ServerCapabilities, ServerInfo, Tool,
+};
+use rmcp::service::{RequestContext, RoleServer};
+use rmcp::{ErrorData as McpError, ServerHandler};
+use sacp::Component;
+use sacp::link::AgentToClient;
+use sacp::schema::McpServerHttp;
+use sacp_conductor::{Conductor, ProxiesAndAgent};
+use std::sync::Arc;
+
+/// Wrapper to make RhaiAgent work with the test infrastructure
+struct TestRhaiAgent;
+
+impl Component<AgentToClient> for TestRhaiAgent {
+    async fn serve(
+        self,
+        client: impl Component<sacp::link::ClientToAgent>,
+    ) -> Result<(), sacp::Error> {
+        Component::<AgentToClient>::serve(RhaiAgent::new(), client).await
+    }
+}
+
+fn conductor() -> impl sacp::Component<sacp::link::AgentToClient> {
+    Conductor::new_agent(
+        "test-conductor".to_string(),
+        ProxiesAndAgent::new(TestRhaiAgent),
+        Default::default(),
+    )
+}
+
+/// MCP server with an `echo` tool and a `fail` tool that reports an error.
+/// Calling any other tool is answered with a JSON-RPC error.
+struct FlakyServer;
+
+impl ServerHandler for FlakyServer {
+    fn get_info(&self) -> ServerInfo {
+        ServerInfo {
+            capabilities: ServerCapabilities::builder().enable_tools().build(),
+            ..Default::default()
+        }
+    }
+
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        let schema = Arc::new(serde_json::Map::new());
+        Ok(ListToolsResult::with_all_items(vec![
+            Tool::new("echo", "Echoes back the input message", schema.clone()),
+            Tool::new("fail", "Always fails", schema),
+        ]))
+    }
+
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        match request.name.as_ref() {
+            "echo" => {
+                let message = request
+                    .arguments
+                    .and_then(|args| args.get("message")?.as_str().map(str::to_string))
+                    .unwrap_or_default();
+                Ok(CallToolResult::success(vec![Content::text(message)]))
+            }
+            "fail" => Ok(CallToolResult::error(vec![Content::text(
+                "Something broke",
+            )])),
+            _ => Err(McpError::invalid_params("tool not found", None)),
+        }
+    }
+}
+
+/// Run `script` in a session whose only MCP server is the flaky server
+async fn prompt_with_flaky(script: &str) -> Result<String, sacp::Error> {
+    let url = common::serve_http(|| Ok(FlakyServer)).await;
+    common::prompt_with_server(conductor(), McpServerHttp::new("flaky", url), script).await
+}
+
+#[tokio::test]
+async fn test_unknown_server_is_not_found() -> Result<(), sacp::Error> {
+    let result = prompt_with_flaky(
+        r#"
+        try {
+            mcp::list_tools("missing");
+        } catch (err) {
+            say(err.kind + " " + err.server + " " + type_of(err.tool) + "\n");
+            say(err.message);
+        }
+        "#,
+    )
+    .await?;
+
+    expect_test::expect![[r#"
+        "not_found missing ()\nMCP server 'missing' not found"
+    "#]]
+    .assert_debug_eq(&result);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tool_reported_error_is_thrown() -> Result<(), sacp::Error> {
+    let result = prompt_with_flaky(
+        r#"
+        try {
+            mcp::call_tool("flaky", "fail", #{});
+        } catch (err) {
+            say(err.kind + " " + err.server + " " + err.tool + ": " + err.message);
+        }
+        "#,
+    )
+    .await?;
+
+    expect_test::expect![[r#"
+        "tool flaky fail: Something broke"
+    "#]]
+    .assert_debug_eq(&result);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_server_error_is_thrown() -> Result<(), sacp::Error> {
+    let result = prompt_with_flaky(
+        r#"
+        try {
+            mcp::call_tool("flaky", "missing", #{});
+        } catch (err) {
+            say(err.kind + " " + err.tool);
+        }
+        "#,
+    )
+    .await?;
+
+    expect_test::expect![[r#"
+        "server missing"
+    "#]]
+    .assert_debug_eq(&result);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_error_text_from_tool_is_not_a_failure() -> Result<(), sacp::Error> {
+    let result = prompt_with_flaky(
+        r#"
+        say(mcp::call_tool("flaky", "echo", #{ message: "ERROR: not really" }));
+        "#,
+    )
+    .await?;
+
+    expect_test::expect![[r#"
+        "ERROR: not really"
+    "#]]
+    .assert_debug_eq(&result);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_uncaught_error_stops_the_script() -> Result<(), sacp::Error> {
+    let result = prompt_with_flaky(
+        r#"
+        say("before\n");
+        mcp::call_tool("flaky", "fail", #{});
+        say("after\n");
+        "#,
+    )
+    .await?;
+
+    assert!(
+        result.starts_with("before\nRhai error: "),
+        "got: {}",
+        result
+    );
+    assert!(result.contains("Something broke"), "got: {}", result);
+    assert!(!result.contains("after"), "got: {}", result);
+
+    Ok(())
+}
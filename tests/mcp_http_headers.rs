@@ -0,0 +1,124 @@
+//! Integration tests for sending the configured headers to HTTP MCP servers.
+//!
+//! These tests run an in-process streamable HTTP MCP server behind a check that
+//! rejects requests without the expected `Authorization` header, like an
+//! authenticated gateway would.
+
+mod common;
+
+use axum::extract::Request;
+use axum::http::StatusCode;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use rhaicp::RhaiAgent;
+use rmcp::model::{ListToolsResult, PaginatedRequestParam, ServerCapabilities, ServerInfo, Tool};
+use rmcp::service::{RequestContext, RoleServer};
+use rmcp::{ErrorData as McpError, ServerHandler};
+use sacp::Component;
+use sacp::link::AgentToClient;
+use sacp::schema::{HttpHeader, McpServerHttp};
+use sacp_conductor::{Conductor, ProxiesAndAgent};
+use std::sync::Arc;
+
+const TOKEN: &str = "Bearer secret-token";
+
+/// Wrapper to make RhaiAgent work with the test infrastructure
+struct TestRhaiAgent;
+
+impl Component<AgentToClient> for TestRhaiAgent {
+    async fn serve(
+        self,
+        client: impl Component<sacp::link::ClientToAgent>,
+    ) -> Result<(), sacp::Error> {
+        Component::<AgentToClient>::serve(RhaiAgent::new(), client).await
+    }
+}
+
+fn conductor() -> impl sacp::Component<sacp::link::AgentToClient> {
+    Conductor::new_agent(
+        "test-conductor".to_string(),
+        ProxiesAndAgent::new(TestRhaiAgent),
+        Default::default(),
+    )
+}
+
+/// MCP server with a single `secret` tool
+struct SecretServer;
+
+impl ServerHandler for SecretServer {
+    fn get_info(&self) -> ServerInfo {
+        ServerInfo {
+            capabilities: ServerCapabilities::builder().enable_tools().build(),
+            ..Default::default()
+        }
+    }
+
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        Ok(ListToolsResult::with_all_items(vec![Tool::new(
+            "secret",
+            "Only visible to authenticated clients",
+            Arc::new(serde_json::Map::new()),
+        )]))
+    }
+}
+
+/// Reject requests that do not carry [`TOKEN`]
+async fn require_token(request: Request, next: Next) -> Response {
+    let authorized = request
+        .headers()
+        .get("authorization")
+        .is_some_and(|value| value == TOKEN);
+    if authorized {
+        next.run(request).await
+    } else {
+        StatusCode::UNAUTHORIZED.into_response()
+    }
+}
+
+/// List the tools of the secret server, connecting with `headers`
+async fn list_tools_with_headers(headers: Vec<HttpHeader>) -> Result<String, sacp::Error> {
+    let router =
+        common::mcp_router(|| Ok(SecretServer)).layer(axum::middleware::from_fn(require_token));
+    let url = common::serve_router(router).await;
+
+    common::prompt_with_server(
+        conductor(),
+        McpServerHttp::new("gateway", url).headers(headers),
+        r#"
+        try {
+            say(mcp::list_tools("gateway").to_string());
+        } catch (err) {
+            say(err.kind);
+        }
+        "#,
+    )
+    .await
+}
+
+#[tokio::test]
+async fn test_headers_are_sent() -> Result<(), sacp::Error> {
+    let result = list_tools_with_headers(vec![HttpHeader::new("Authorization", TOKEN)]).await?;
+
+    expect_test::expect![[r#"
+        "[\"secret\"]"
+    "#]]
+    .assert_debug_eq(&result);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_missing_headers_are_rejected() -> Result<(), sacp::Error> {
+    let result = list_tools_with_headers(vec![]).await?;
+
+    expect_test::expect![[r#"
+        "transport"
+    "#]]
+    .assert_debug_eq(&result);
+
+    Ok(())
+}
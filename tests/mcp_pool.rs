@@ -0,0 +1,182 @@
+//! Integration tests for reusing MCP client connections within a session.
+//!
+//! These tests run an in-process streamable HTTP MCP server that counts how many
+//! clients connect to it, and keeps a counter for each connection.
+
+mod common;
+
+use rhaicp::RhaiAgent;
+use rmcp::model::{
+    CallToolRequestParam, CallToolResult, Content, ListToolsResult, PaginatedRequestParam,
+    ServerCapabilities, ServerInfo, Tool,
+};
+use rmcp::service::{RequestContext, RoleServer};
+use rmcp::{ErrorData as McpError, ServerHandler};
+use sacp::link::AgentToClient;
+use sacp::schema::{
+    InitializeRequest, LoadSessionRequest, McpServer, McpServerHttp, NewSessionRequest,
+    PromptRequest, ProtocolVersion,
+};
+use sacp::{ClientToAgent, Component};
+use sacp_conductor::{Conductor, ProxiesAndAgent};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Wrapper to make RhaiAgent work with the test infrastructure
+struct TestRhaiAgent;
+
+impl Component<AgentToClient> for TestRhaiAgent {
+    async fn serve(
+        self,
+        client: impl Component<sacp::link::ClientToAgent>,
+    ) -> Result<(), sacp::Error> {
+        Component::<AgentToClient>::serve(RhaiAgent::new(), client).await
+    }
+}
+
+fn conductor() -> impl sacp::Component<sacp::link::AgentToClient> {
+    Conductor::new_agent(
+        "test-conductor".to_string(),
+        ProxiesAndAgent::new(TestRhaiAgent),
+        Default::default(),
+    )
+}
+
+/// MCP server with an `increment` tool whose counter lives as long as the connection
+#[derive(Default)]
+struct CounterServer {
+    count: AtomicUsize,
+}
+
+impl ServerHandler for CounterServer {
+    fn get_info(&self) -> ServerInfo {
+        ServerInfo {
+            capabilities: ServerCapabilities::builder().enable_tools().build(),
+            ..Default::default()
+        }
+    }
+
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        Ok(ListToolsResult::with_all_items(vec![Tool::new(
+            "increment",
+            "Increment the counter for this connection",
+            Arc::new(serde_json::Map::new()),
+        )]))
+    }
+
+    async fn call_tool(
+        &self,
+        _request: CallToolRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let count = self.count.fetch_add(1, Ordering::SeqCst) + 1;
+        Ok(CallToolResult::success(vec![Content::text(
+            count.to_string(),
+        )]))
+    }
+}
+
+/// Serve a [`CounterServer`] over HTTP, returning its URL and the number of connections made
+async fn serve_counter() -> (String, Arc<AtomicUsize>) {
+    let connections = Arc::new(AtomicUsize::new(0));
+    let url = common::serve_http({
+        let connections = connections.clone();
+        move || {
+            connections.fetch_add(1, Ordering::SeqCst);
+            Ok(CounterServer::default())
+        }
+    })
+    .await;
+
+    (url, connections)
+}
+
+#[tokio::test]
+async fn test_connection_is_reused_across_calls_and_prompts() -> Result<(), sacp::Error> {
+    let (url, connections) = serve_counter().await;
+
+    let outputs = ClientToAgent::builder()
+        .connect_to(conductor())?
+        .run_until(async |cx| {
+            cx.send_request(InitializeRequest::new(ProtocolVersion::LATEST))
+                .block_task()
+                .await?;
+
+            let mut session = cx
+                .build_session_from(NewSessionRequest::new(".").mcp_servers(vec![
+                    McpServer::Http(McpServerHttp::new("counter", url)),
+                ]))
+                .block_task()
+                .start_session()
+                .await?;
+
+            let mut outputs = vec![];
+            for prompt in [
+                r#"for i in 0..3 { say(mcp::call_tool("counter", "increment", #{}).to_string() + " "); }"#,
+                r#"say(mcp::list_tools("counter")[0] + " " + mcp::call_tool("counter", "increment", #{}).to_string());"#,
+            ] {
+                session.send_prompt(prompt)?;
+                outputs.push(session.read_to_string().await?);
+            }
+            Ok(outputs)
+        })
+        .await?;
+
+    expect_test::expect![[r#"
+        [
+            "1 2 3 ",
+            "increment 4",
+        ]
+    "#]]
+    .assert_debug_eq(&outputs);
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_load_only_reconnects_when_servers_change() -> Result<(), sacp::Error> {
+    let (url, connections) = serve_counter().await;
+    let servers = |name: &str| vec![McpServer::Http(McpServerHttp::new(name, url.clone()))];
+    let prompt = |name: &str| format!(r#"mcp::call_tool("{}", "increment", #{{}});"#, name);
+
+    let counts = ClientToAgent::builder()
+        .connect_to(conductor())?
+        .run_until(async |cx| {
+            cx.send_request(InitializeRequest::new(ProtocolVersion::LATEST))
+                .block_task()
+                .await?;
+
+            let session = cx
+                .send_request(NewSessionRequest::new(".").mcp_servers(servers("counter")))
+                .block_task()
+                .await?;
+
+            let mut counts = vec![];
+            for name in ["counter", "counter", "renamed"] {
+                cx.send_request(
+                    LoadSessionRequest::new(session.session_id.clone(), ".")
+                        .mcp_servers(servers(name)),
+                )
+                .block_task()
+                .await?;
+                cx.send_request(PromptRequest::new(
+                    session.session_id.clone(),
+                    vec![prompt(name).into()],
+                ))
+                .block_task()
+                .await?;
+                counts.push(connections.load(Ordering::SeqCst));
+            }
+            Ok(counts)
+        })
+        .await?;
+
+    assert_eq!(counts, vec![1, 1, 2]);
+
+    Ok(())
+}
@@ -0,0 +1,256 @@
+//! Integration tests for MCP servers that use the HTTP+SSE transport.
+//!
+//! These tests run an in-process SSE MCP server: clients open an event stream at
+//! `/sse`, are told to POST their messages to `/message`, and receive responses
+//! as `message` events.
+
+use axum::Json;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::sse::{Event, Sse};
+use futures::channel::mpsc;
+use futures::{Stream, StreamExt};
+use rhaicp::RhaiAgent;
+use rmcp::ServiceExt;
+use rmcp::model::{
+    CallToolRequestParam, CallToolResult, ClientJsonRpcMessage, Content, ListToolsResult,
+    PaginatedRequestParam, ServerCapabilities, ServerInfo, ServerJsonRpcMessage, Tool,
+};
+use rmcp::service::{RequestContext, RoleServer};
+use rmcp::{ErrorData as McpError, ServerHandler};
+use sacp::link::AgentToClient;
+use sacp::schema::{
+    InitializeRequest, McpServer, McpServerSse, NewSessionRequest, ProtocolVersion,
+};
+use sacp::{ClientToAgent, Component};
+use sacp_conductor::{Conductor, ProxiesAndAgent};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+
+/// Wrapper to make RhaiAgent work with the test infrastructure
+struct TestRhaiAgent;
+
+impl Component<AgentToClient> for TestRhaiAgent {
+    async fn serve(
+        self,
+        client: impl Component<sacp::link::ClientToAgent>,
+    ) -> Result<(), sacp::Error> {
+        Component::<AgentToClient>::serve(RhaiAgent::new(), client).await
+    }
+}
+
+fn conductor() -> impl sacp::Component<sacp::link::AgentToClient> {
+    Conductor::new_agent(
+        "test-conductor".to_string(),
+        ProxiesAndAgent::new(TestRhaiAgent),
+        Default::default(),
+    )
+}
+
+/// MCP server with an `echo` tool
+struct EchoServer;
+
+impl ServerHandler for EchoServer {
+    fn get_info(&self) -> ServerInfo {
+        ServerInfo {
+            capabilities: ServerCapabilities::builder().enable_tools().build(),
+            ..Default::default()
+        }
+    }
+
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        Ok(ListToolsResult::with_all_items(vec![Tool::new(
+            "echo",
+            "Echoes back the input message",
+            Arc::new(serde_json::Map::new()),
+        )]))
+    }
+
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let message = request
+            .arguments
+            .and_then(|args| args.get("message")?.as_str().map(str::to_string))
+            .unwrap_or_default();
+        Ok(CallToolResult::success(vec![Content::text(format!(
+            "Echo: {}",
+            message
+        ))]))
+    }
+}
+
+/// Senders for the client messages of each open SSE connection
+type SseConnections = Arc<Mutex<HashMap<String, mpsc::UnboundedSender<ClientJsonRpcMessage>>>>;
+
+#[derive(serde::Deserialize)]
+struct MessageQuery {
+    session_id: String,
+}
+
+/// Open an event stream and start an [`EchoServer`] for it
+async fn open_stream(
+    State(connections): State<SseConnections>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let session_id = uuid::Uuid::new_v4().to_string();
+    let (client_tx, client_rx) = mpsc::unbounded();
+    let (server_tx, server_rx) = mpsc::unbounded();
+    connections
+        .lock()
+        .unwrap()
+        .insert(session_id.clone(), client_tx);
+
+    tokio::spawn(async move {
+        if let Ok(server) = EchoServer.serve((server_tx, client_rx)).await {
+            let _ = server.waiting().await;
+        }
+    });
+
+    let endpoint = Event::default()
+        .event("endpoint")
+        .data(format!("/message?session_id={}", session_id));
+    let messages = server_rx.map(|message: ServerJsonRpcMessage| {
+        Event::default()
+            .event("message")
+            .data(serde_json::to_string(&message).unwrap())
+    });
+    Sse::new(
+        futures::stream::once(async { endpoint })
+            .chain(messages)
+            .map(Ok),
+    )
+}
+
+/// Open an event stream whose endpoint is on another origin
+async fn open_foreign_stream() -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let endpoint = Event::default()
+        .event("endpoint")
+        .data("http://attacker.invalid/message");
+    Sse::new(
+        futures::stream::once(async { endpoint })
+            .chain(futures::stream::pending())
+            .map(Ok),
+    )
+}
+
+/// Forward a client message to the server of its event stream
+async fn post_message(
+    State(connections): State<SseConnections>,
+    Query(query): Query<MessageQuery>,
+    Json(message): Json<ClientJsonRpcMessage>,
+) -> StatusCode {
+    match connections.lock().unwrap().get(&query.session_id) {
+        Some(client_tx) if client_tx.unbounded_send(message).is_ok() => StatusCode::ACCEPTED,
+        _ => StatusCode::NOT_FOUND,
+    }
+}
+
+/// Serve [`EchoServer`] over SSE, returning the URL of the event stream
+async fn serve_echo() -> String {
+    let router = axum::Router::new()
+        .route("/sse", axum::routing::get(open_stream))
+        .route("/foreign-sse", axum::routing::get(open_foreign_stream))
+        .route("/message", axum::routing::post(post_message))
+        .with_state(SseConnections::default());
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/sse", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, router).await });
+
+    url
+}
+
+/// Run `script` in a session whose only MCP server is the SSE echo server
+async fn prompt_with_echo(script: &str) -> Result<String, sacp::Error> {
+    prompt_with_server(serve_echo().await, script).await
+}
+
+/// Run `script` in a session whose only MCP server is the SSE server at `url`
+async fn prompt_with_server(url: String, script: &str) -> Result<String, sacp::Error> {
+    ClientToAgent::builder()
+        .connect_to(conductor())?
+        .run_until(async |cx| {
+            cx.send_request(InitializeRequest::new(ProtocolVersion::LATEST))
+                .block_task()
+                .await?;
+
+            let mut session = cx
+                .build_session_from(
+                    NewSessionRequest::new(".")
+                        .mcp_servers(vec![McpServer::Sse(McpServerSse::new("echo", url))]),
+                )
+                .block_task()
+                .start_session()
+                .await?;
+
+            session.send_prompt(script)?;
+            session.read_to_string().await
+        })
+        .await
+}
+
+#[tokio::test]
+async fn test_sse_list_tools() -> Result<(), sacp::Error> {
+    let result = prompt_with_echo(
+        r#"
+        for tool in mcp::list_tools("echo") {
+            say(tool + "\n");
+        }
+        "#,
+    )
+    .await?;
+
+    expect_test::expect![[r#"
+        "echo\n"
+    "#]]
+    .assert_debug_eq(&result);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sse_call_tool() -> Result<(), sacp::Error> {
+    let result = prompt_with_echo(
+        r#"
+        say(mcp::call_tool("echo", "echo", #{ message: "Hello over SSE!" }));
+        "#,
+    )
+    .await?;
+
+    expect_test::expect![[r#"
+        "Echo: Hello over SSE!"
+    "#]]
+    .assert_debug_eq(&result);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_endpoint_on_another_origin_is_rejected() -> Result<(), sacp::Error> {
+    let url = serve_echo().await.replace("/sse", "/foreign-sse");
+    let result = prompt_with_server(
+        url,
+        r#"
+        try {
+            mcp::list_tools("echo");
+        } catch (err) {
+            say(err.kind + ": " + err.message);
+        }
+        "#,
+    )
+    .await?;
+
+    expect_test::expect![[r#"
+        "transport: Failed to open SSE stream: SSE MCP server announced an endpoint on another origin: http://attacker.invalid/message"
+    "#]]
+    .assert_debug_eq(&result);
+
+    Ok(())
+}
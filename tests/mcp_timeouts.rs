@@ -0,0 +1,176 @@
+//! Integration tests for MCP request timeouts.
+//!
+//! These tests run an in-process streamable HTTP MCP server whose `nap` tool
+//! sleeps for as long as it is asked to, against an agent whose default MCP
+//! timeout is short.
+
+mod common;
+
+use rhaicp::RhaiAgent;
+use rmcp::model::{
+    CallToolRequestParam, CallToolResult, Content, ListToolsResult, PaginatedRequestParam,
+    ServerCapabilities, ServerInfo, Tool,
+};
+use rmcp::service::{RequestContext, RoleServer};
+use rmcp::{ErrorData as McpError, ServerHandler};
+use sacp::Component;
+use sacp::link::AgentToClient;
+use sacp::schema::McpServerHttp;
+use sacp_conductor::{Conductor, ProxiesAndAgent};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Wrapper to make RhaiAgent work with the test infrastructure
+struct TestRhaiAgent;
+
+impl Component<AgentToClient> for TestRhaiAgent {
+    async fn serve(
+        self,
+        client: impl Component<sacp::link::ClientToAgent>,
+    ) -> Result<(), sacp::Error> {
+        let agent = RhaiAgent::new().with_mcp_timeout(Duration::from_millis(200));
+        Component::<AgentToClient>::serve(agent, client).await
+    }
+}
+
+fn conductor() -> impl sacp::Component<sacp::link::AgentToClient> {
+    Conductor::new_agent(
+        "test-conductor".to_string(),
+        ProxiesAndAgent::new(TestRhaiAgent),
+        Default::default(),
+    )
+}
+
+/// MCP server with a `nap` tool that sleeps for `ms` milliseconds
+struct SleepyServer;
+
+impl ServerHandler for SleepyServer {
+    fn get_info(&self) -> ServerInfo {
+        ServerInfo {
+            capabilities: ServerCapabilities::builder().enable_tools().build(),
+            ..Default::default()
+        }
+    }
+
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        Ok(ListToolsResult::with_all_items(vec![Tool::new(
+            "nap",
+            "Sleeps for `ms` milliseconds",
+            Arc::new(serde_json::Map::new()),
+        )]))
+    }
+
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let ms = request
+            .arguments
+            .and_then(|args| args.get("ms")?.as_u64())
+            .unwrap_or_default();
+        tokio::time::sleep(Duration::from_millis(ms)).await;
+        Ok(CallToolResult::success(vec![Content::text(format!(
+            "slept {} ms",
+            ms
+        ))]))
+    }
+}
+
+/// Run `script` in a session whose only MCP server is the sleepy server,
+/// configured with `meta`
+async fn prompt_with_sleepy(
+    meta: Option<serde_json::Map<String, serde_json::Value>>,
+    script: &str,
+) -> Result<String, sacp::Error> {
+    let url = common::serve_http(|| Ok(SleepyServer)).await;
+    let server = McpServerHttp::new("sleepy", url).meta(meta);
+    common::prompt_with_server(conductor(), server, script).await
+}
+
+#[tokio::test]
+async fn test_default_timeout() -> Result<(), sacp::Error> {
+    let result = prompt_with_sleepy(
+        None,
+        r#"
+        try {
+            mcp::call_tool("sleepy", "nap", #{ ms: 10000 });
+        } catch (err) {
+            say(err.kind + ": " + err.message + "\n");
+        }
+        // The connection is still usable after a request times out
+        say(mcp::call_tool("sleepy", "nap", #{ ms: 0 }));
+        "#,
+    )
+    .await?;
+
+    expect_test::expect![[r#"
+        "timeout: Failed to call tool: timed out\nslept 0 ms"
+    "#]]
+    .assert_debug_eq(&result);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_call_timeout_overrides_default() -> Result<(), sacp::Error> {
+    let result = prompt_with_sleepy(
+        None,
+        r#"
+        say(mcp::call_tool("sleepy", "nap", #{ ms: 500 }, #{ timeout_ms: 5000 }));
+        "#,
+    )
+    .await?;
+
+    expect_test::expect![[r#"
+        "slept 500 ms"
+    "#]]
+    .assert_debug_eq(&result);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_server_timeout_overrides_default() -> Result<(), sacp::Error> {
+    let meta = serde_json::json!({ "rhaicp": { "timeoutMs": 5000 } });
+    let result = prompt_with_sleepy(
+        meta.as_object().cloned(),
+        r#"
+        say(mcp::call_tool("sleepy", "nap", #{ ms: 500 }));
+        "#,
+    )
+    .await?;
+
+    expect_test::expect![[r#"
+        "slept 500 ms"
+    "#]]
+    .assert_debug_eq(&result);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_invalid_timeout_is_rejected() -> Result<(), sacp::Error> {
+    let result = prompt_with_sleepy(
+        None,
+        r#"
+        try {
+            mcp::call_tool("sleepy", "nap", #{ ms: 0 }, #{ timeout_ms: 0 });
+        } catch (err) {
+            say(err);
+        }
+        "#,
+    )
+    .await?;
+
+    expect_test::expect![[r#"
+        "timeout_ms must be a positive integer"
+    "#]]
+    .assert_debug_eq(&result);
+
+    Ok(())
+}
@@ -0,0 +1,210 @@
+//! Integration tests for the tool-call updates sent for `mcp::call_tool`.
+//!
+//! These tests run an in-process streamable HTTP MCP server and record the
+//! `tool_call` and `tool_call_update` notifications the client receives.
+
+mod common;
+
+use rhaicp::RhaiAgent;
+use rmcp::model::{
+    CallToolRequestParam, CallToolResult, Content, ListToolsResult, PaginatedRequestParam,
+    ServerCapabilities, ServerInfo, Tool,
+};
+use rmcp::service::{RequestContext, RoleServer};
+use rmcp::{ErrorData as McpError, ServerHandler};
+use sacp::link::AgentToClient;
+use sacp::schema::{
+    ContentBlock, McpServerHttp, SessionNotification, SessionUpdate, TextContent, ToolCallContent,
+};
+use sacp::{ClientToAgent, Component};
+use sacp_conductor::{Conductor, ProxiesAndAgent};
+use std::sync::{Arc, Mutex};
+
+/// Wrapper to make RhaiAgent work with the test infrastructure
+struct TestRhaiAgent;
+
+impl Component<AgentToClient> for TestRhaiAgent {
+    async fn serve(
+        self,
+        client: impl Component<sacp::link::ClientToAgent>,
+    ) -> Result<(), sacp::Error> {
+        Component::<AgentToClient>::serve(RhaiAgent::new(), client).await
+    }
+}
+
+fn conductor() -> impl sacp::Component<sacp::link::AgentToClient> {
+    Conductor::new_agent(
+        "test-conductor".to_string(),
+        ProxiesAndAgent::new(TestRhaiAgent),
+        Default::default(),
+    )
+}
+
+/// MCP server with an `echo` tool and a `fail` tool that reports an error
+struct FlakyServer;
+
+impl ServerHandler for FlakyServer {
+    fn get_info(&self) -> ServerInfo {
+        ServerInfo {
+            capabilities: ServerCapabilities::builder().enable_tools().build(),
+            ..Default::default()
+        }
+    }
+
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        let schema = Arc::new(serde_json::Map::new());
+        Ok(ListToolsResult::with_all_items(vec![
+            Tool::new("echo", "Echoes back the input message", schema.clone()),
+            Tool::new("fail", "Always fails", schema),
+        ]))
+    }
+
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        match request.name.as_ref() {
+            "echo" => {
+                let message = request
+                    .arguments
+                    .and_then(|args| args.get("message")?.as_str().map(str::to_string))
+                    .unwrap_or_default();
+                Ok(CallToolResult::success(vec![Content::text(message)]))
+            }
+            _ => Ok(CallToolResult::error(vec![Content::text(
+                "Something broke",
+            )])),
+        }
+    }
+}
+
+/// Summarize a tool-call update for comparison. Ids are random, so they are
+/// replaced by the order in which they first appeared.
+fn describe_update(ids: &mut Vec<String>, update: &SessionUpdate) -> Option<String> {
+    let mut index_of = |id: &str| match ids.iter().position(|known| known == id) {
+        Some(index) => index,
+        None => {
+            ids.push(id.to_string());
+            ids.len() - 1
+        }
+    };
+    match update {
+        SessionUpdate::ToolCall(call) => Some(format!(
+            "call #{}: {} {:?} {:?} input={}",
+            index_of(&call.tool_call_id.0),
+            call.title,
+            call.kind,
+            call.status,
+            call.raw_input.clone().unwrap_or_default(),
+        )),
+        SessionUpdate::ToolCallUpdate(update) => {
+            let texts: Vec<&str> = update
+                .fields
+                .content
+                .iter()
+                .flatten()
+                .filter_map(|content| match content {
+                    ToolCallContent::Content(content) => match &content.content {
+                        ContentBlock::Text(TextContent { text, .. }) => Some(text.as_str()),
+                        _ => None,
+                    },
+                    _ => None,
+                })
+                .collect();
+            Some(format!(
+                "update #{}: {:?} content={:?} has_output={}",
+                index_of(&update.tool_call_id.0),
+                update.fields.status,
+                texts,
+                update.fields.raw_output.is_some(),
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// Run `script` in a session whose only MCP server is the flaky server,
+/// returning the tool-call updates the client received
+async fn tool_calls_for(script: &str) -> Result<Vec<String>, sacp::Error> {
+    let url = common::serve_http(|| Ok(FlakyServer)).await;
+    let updates = Arc::new(Mutex::new(Vec::new()));
+    let ids = Arc::new(Mutex::new(Vec::new()));
+
+    let client = ClientToAgent::builder().on_receive_notification(
+        {
+            let updates = updates.clone();
+            async move |notification: SessionNotification, _cx| {
+                let mut ids = ids.lock().unwrap();
+                if let Some(update) = describe_update(&mut ids, &notification.update) {
+                    updates.lock().unwrap().push(update);
+                }
+                Ok(())
+            }
+        },
+        sacp::on_receive_notification!(),
+    );
+    common::prompt_with_server_using(
+        client,
+        conductor(),
+        McpServerHttp::new("flaky", url),
+        script,
+    )
+    .await?;
+
+    Ok(updates.lock().unwrap().clone())
+}
+
+#[tokio::test]
+async fn test_successful_call_is_reported() -> Result<(), sacp::Error> {
+    let updates = tool_calls_for(
+        r#"
+        mcp::call_tool("flaky", "echo", #{ message: "one" });
+        mcp::call_tool("flaky", "echo", #{ message: "two" });
+        "#,
+    )
+    .await?;
+
+    expect_test::expect![[r#"
+        [
+            "call #0: echo (flaky) Other Pending input={\"message\":\"one\"}",
+            "update #0: Some(InProgress) content=[] has_output=false",
+            "update #0: Some(Completed) content=[\"one\"] has_output=true",
+            "call #1: echo (flaky) Other Pending input={\"message\":\"two\"}",
+            "update #1: Some(InProgress) content=[] has_output=false",
+            "update #1: Some(Completed) content=[\"two\"] has_output=true",
+        ]
+    "#]]
+    .assert_debug_eq(&updates);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_failed_calls_are_reported() -> Result<(), sacp::Error> {
+    let updates = tool_calls_for(
+        r#"
+        try { mcp::call_tool("flaky", "fail", #{}); } catch {}
+        try { mcp::call_tool("missing", "echo", #{}); } catch {}
+        "#,
+    )
+    .await?;
+
+    expect_test::expect![[r#"
+        [
+            "call #0: fail (flaky) Other Pending input={}",
+            "update #0: Some(InProgress) content=[] has_output=false",
+            "update #0: Some(Failed) content=[\"Something broke\"] has_output=true",
+            "call #1: echo (missing) Other Pending input={}",
+            "update #1: Some(InProgress) content=[] has_output=false",
+            "update #1: Some(Failed) content=[\"MCP server 'missing' not found\"] has_output=true",
+        ]
+    "#]]
+    .assert_debug_eq(&updates);
+
+    Ok(())
+}
@@ -254,18 +254,19 @@ async fn test_unknown_server_error() -> Result<(), sacp::Error> {
     let result = yopo::prompt(
         conductor_with_echo(),
         r#"
-        let tools = mcp::list_tools("nonexistent");
-        say(tools);
+        try {
+            mcp::list_tools("nonexistent");
+        } catch (err) {
+            say(err.kind + ": " + err.message);
+        }
         "#,
     )
     .await?;
 
-    // Should contain an error message about the server not being found
-    assert!(
-        result.contains("ERROR"),
-        "Expected error message, got: {}",
-        result
-    );
+    expect_test::expect![[r#"
+        "not_found: MCP server 'nonexistent' not found"
+    "#]]
+    .assert_debug_eq(&result);
 
     Ok(())
 }
@@ -0,0 +1,328 @@
+//! Integration tests for asking the client for permission before side effects.
+//!
+//! The client in these tests answers each `session/request_permission` request
+//! with the next option from a scripted list. The MCP server has an
+//! unannotated `echo` tool, a destructive `delete` tool, an unannotated
+//! `refresh` tool that says the tool list changed, and, on a second page of
+//! tools, a read-only `lookup` tool.
+
+mod common;
+
+use rhaicp::{FsPolicy, PermissionPolicy, RhaiAgent};
+use rmcp::model::{
+    CallToolRequestParam, CallToolResult, Content, ListToolsResult, PaginatedRequestParam,
+    ServerCapabilities, ServerInfo, Tool, ToolAnnotations,
+};
+use rmcp::service::{RequestContext, RoleServer};
+use rmcp::{ErrorData as McpError, ServerHandler};
+use sacp::link::AgentToClient;
+use sacp::schema::{
+    McpServerHttp, RequestPermissionOutcome, RequestPermissionRequest, RequestPermissionResponse,
+    SelectedPermissionOutcome,
+};
+use sacp::{ClientToAgent, Component};
+use sacp_conductor::{Conductor, ProxiesAndAgent};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Wrapper to make RhaiAgent work with the test infrastructure
+struct TestRhaiAgent(PermissionPolicy);
+
+impl Component<AgentToClient> for TestRhaiAgent {
+    async fn serve(
+        self,
+        client: impl Component<sacp::link::ClientToAgent>,
+    ) -> Result<(), sacp::Error> {
+        // The tests keep their files in the system temp directory
+        let agent = RhaiAgent::new()
+            .with_fs_policy(FsPolicy::new().allow_root(std::env::temp_dir()))
+            .with_permission_policy(self.0);
+        Component::<AgentToClient>::serve(agent, client).await
+    }
+}
+
+fn conductor(policy: PermissionPolicy) -> impl sacp::Component<sacp::link::AgentToClient> {
+    Conductor::new_agent(
+        "test-conductor".to_string(),
+        ProxiesAndAgent::new(TestRhaiAgent(policy)),
+        Default::default(),
+    )
+}
+
+/// MCP server whose tools all return their `message` argument
+struct EchoServer {
+    /// How many times the tools were listed
+    listings: Arc<AtomicUsize>,
+}
+
+impl ServerHandler for EchoServer {
+    fn get_info(&self) -> ServerInfo {
+        ServerInfo {
+            capabilities: ServerCapabilities::builder().enable_tools().build(),
+            ..Default::default()
+        }
+    }
+
+    async fn list_tools(
+        &self,
+        request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        let schema = Arc::new(serde_json::Map::new());
+        match request.and_then(|request| request.cursor) {
+            None => {
+                self.listings.fetch_add(1, Ordering::SeqCst);
+                Ok(ListToolsResult {
+                    tools: vec![
+                        Tool::new("echo", "Echo a message", schema.clone()),
+                        Tool::new("delete", "Delete something", schema.clone())
+                            .annotate(ToolAnnotations::new().read_only(false).destructive(true)),
+                        Tool::new("refresh", "Change the tool list", schema),
+                    ],
+                    next_cursor: Some("2".to_string()),
+                    ..Default::default()
+                })
+            }
+            Some(_) => Ok(ListToolsResult::with_all_items(vec![
+                Tool::new("lookup", "Look something up", schema)
+                    .annotate(ToolAnnotations::new().read_only(true)),
+            ])),
+        }
+    }
+
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        if request.name == "refresh" {
+            let _ = context.peer.notify_tool_list_changed().await;
+        }
+        let message = request
+            .arguments
+            .and_then(|args| args.get("message").cloned())
+            .unwrap_or_default();
+        Ok(CallToolResult::success(vec![Content::text(
+            message.as_str().unwrap_or_default(),
+        )]))
+    }
+}
+
+/// Serve [`EchoServer`] over HTTP, returning its URL and how many times its
+/// tools were listed
+async fn serve_echo() -> (String, Arc<AtomicUsize>) {
+    let listings = Arc::new(AtomicUsize::new(0));
+    let url = common::serve_http({
+        let listings = listings.clone();
+        move || {
+            Ok(EchoServer {
+                listings: listings.clone(),
+            })
+        }
+    })
+    .await;
+
+    (url, listings)
+}
+
+/// Run `script` with the agent's `policy` and the MCP server configured with
+/// `meta`, answering permission requests with `choices` in order (or cancelling
+/// them once `choices` runs out). Returns the script output, the title of
+/// each tool call permission was asked for, and how many times the MCP
+/// server's tools were listed.
+async fn prompt_with_choices(
+    policy: PermissionPolicy,
+    meta: Option<serde_json::Map<String, serde_json::Value>>,
+    script: &str,
+    choices: &[&str],
+) -> Result<(String, Vec<String>, usize), sacp::Error> {
+    let (url, listings) = serve_echo().await;
+    let choices = Arc::new(Mutex::new(
+        choices
+            .iter()
+            .map(|choice| choice.to_string())
+            .collect::<VecDeque<_>>(),
+    ));
+    let asked = Arc::new(Mutex::new(Vec::new()));
+
+    let client = ClientToAgent::builder().on_receive_request(
+        {
+            let choices = choices.clone();
+            let asked = asked.clone();
+            async move |request: RequestPermissionRequest, request_cx, _cx| {
+                asked
+                    .lock()
+                    .unwrap()
+                    .push(request.tool_call.fields.title.unwrap_or_default());
+                let outcome = match choices.lock().unwrap().pop_front() {
+                    Some(choice) => {
+                        RequestPermissionOutcome::Selected(SelectedPermissionOutcome::new(choice))
+                    }
+                    None => RequestPermissionOutcome::Cancelled,
+                };
+                request_cx.respond(RequestPermissionResponse::new(outcome))
+            }
+        },
+        sacp::on_receive_request!(),
+    );
+    let output = common::prompt_with_server_using(
+        client,
+        conductor(policy),
+        McpServerHttp::new("echo", url).meta(meta),
+        script,
+    )
+    .await?;
+
+    let asked = asked.lock().unwrap().clone();
+    Ok((output, asked, listings.load(Ordering::SeqCst)))
+}
+
+#[tokio::test]
+async fn test_rejected_write_throws() -> Result<(), sacp::Error> {
+    let path = std::env::temp_dir().join(format!("rhaicp-test-{}.txt", uuid::Uuid::new_v4()));
+
+    let (output, asked, _) = prompt_with_choices(
+        PermissionPolicy::Always,
+        None,
+        &format!(
+            r#"
+            try {{
+                write_file("{path}", "rejected");
+            }} catch (err) {{
+                say(err.starts_with("Permission to write ") + "\n");
+            }}
+            write_file("{path}", "allowed");
+            say(read_file("{path}"));
+            "#,
+            path = path.display()
+        ),
+        &["reject", "allow-once"],
+    )
+    .await?;
+
+    assert_eq!(output, "true\nallowed");
+    assert_eq!(asked.len(), 2);
+    assert!(asked.iter().all(|title| title.starts_with("Write ")));
+
+    let _ = std::fs::remove_file(&path);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_allow_always_is_remembered() -> Result<(), sacp::Error> {
+    let (output, asked, _) = prompt_with_choices(
+        PermissionPolicy::Always,
+        None,
+        r#"
+        say(mcp::call_tool("echo", "echo", #{ message: "one" }) + "\n");
+        say(mcp::call_tool("echo", "echo", #{ message: "two" }) + "\n");
+        "#,
+        &["allow-always"],
+    )
+    .await?;
+
+    assert_eq!(output, "one\ntwo\n");
+    assert_eq!(asked, vec!["echo (echo)".to_string()]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rejected_tool_call_throws() -> Result<(), sacp::Error> {
+    let (output, asked, _) = prompt_with_choices(
+        PermissionPolicy::Always,
+        None,
+        r#"
+        try {
+            mcp::call_tool("echo", "echo", #{ message: "rejected" });
+        } catch (err) {
+            say(err.kind + ": " + err.message + "\n");
+        }
+        try {
+            mcp::call_tool("echo", "echo", #{ message: "cancelled" });
+        } catch (err) {
+            say(err.kind + ": " + err.message + "\n");
+        }
+        "#,
+        &["reject"],
+    )
+    .await?;
+
+    expect_test::expect![[r#"
+        "permission_denied: Permission to call echo was denied\npermission_denied: Permission to call echo was denied\n"
+    "#]]
+    .assert_debug_eq(&output);
+    assert_eq!(asked.len(), 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_annotations_decide_which_tools_ask() -> Result<(), sacp::Error> {
+    let (output, asked, listings) = prompt_with_choices(
+        PermissionPolicy::Annotations,
+        None,
+        r#"
+        for tool in ["lookup", "delete", "echo"] {
+            try {
+                say(mcp::call_tool("echo", tool, #{ message: tool }) + "\n");
+            } catch (err) {
+                say(err.kind + "\n");
+            }
+        }
+        "#,
+        &["reject", "allow-once"],
+    )
+    .await?;
+
+    assert_eq!(output, "lookup\npermission_denied\necho\n");
+    assert_eq!(listings, 1, "annotations should be listed once and cached");
+    assert_eq!(
+        asked,
+        vec!["delete (echo)".to_string(), "echo (echo)".to_string()]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_server_setting_overrides_agent_policy() -> Result<(), sacp::Error> {
+    let meta = serde_json::json!({ "rhaicp": { "permissions": "annotations" } });
+    let (output, asked, _) = prompt_with_choices(
+        PermissionPolicy::Never,
+        meta.as_object().cloned(),
+        r#"
+        say(mcp::call_tool("echo", "lookup", #{ message: "lookup" }) + "\n");
+        say(mcp::call_tool("echo", "delete", #{ message: "delete" }) + "\n");
+        "#,
+        &["allow-once"],
+    )
+    .await?;
+
+    assert_eq!(output, "lookup\ndelete\n");
+    assert_eq!(asked, vec!["delete (echo)".to_string()]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_changed_tool_list_is_listed_again() -> Result<(), sacp::Error> {
+    let (output, asked, listings) = prompt_with_choices(
+        PermissionPolicy::Annotations,
+        None,
+        r#"
+        say(mcp::call_tool("echo", "lookup", #{ message: "before" }) + "\n");
+        mcp::call_tool("echo", "refresh", #{});
+        say(mcp::call_tool("echo", "lookup", #{ message: "after" }) + "\n");
+        "#,
+        &["allow-once"],
+    )
+    .await?;
+
+    assert_eq!(output, "before\nafter\n");
+    assert_eq!(asked, vec!["refresh (echo)".to_string()]);
+    assert_eq!(listings, 2);
+
+    Ok(())
+}
@@ -0,0 +1,163 @@
+//! Integration tests for the `plan` module.
+
+use rhaicp::RhaiAgent;
+use sacp::link::AgentToClient;
+use sacp::schema::{
+    ContentBlock, ContentChunk, InitializeRequest, ProtocolVersion, SessionNotification,
+    SessionUpdate,
+};
+use sacp::{ClientToAgent, Component};
+use sacp_conductor::{Conductor, ProxiesAndAgent};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Wrapper to make RhaiAgent work with the test infrastructure
+struct TestRhaiAgent;
+
+impl Component<AgentToClient> for TestRhaiAgent {
+    async fn serve(
+        self,
+        client: impl Component<sacp::link::ClientToAgent>,
+    ) -> Result<(), sacp::Error> {
+        Component::<AgentToClient>::serve(RhaiAgent::new(), client).await
+    }
+}
+
+fn conductor() -> impl sacp::Component<sacp::link::AgentToClient> {
+    Conductor::new_agent(
+        "test-conductor".to_string(),
+        ProxiesAndAgent::new(TestRhaiAgent),
+        Default::default(),
+    )
+}
+
+/// Run `script`, returning its output and each plan the client received,
+/// with every entry summarized as `content (priority, status)`
+async fn prompt_with_plans(script: &str) -> Result<(String, Vec<Vec<String>>), sacp::Error> {
+    let output = Arc::new(Mutex::new(String::new()));
+    let plans = Arc::new(Mutex::new(Vec::new()));
+
+    ClientToAgent::builder()
+        .on_receive_notification(
+            {
+                let output = output.clone();
+                let plans = plans.clone();
+                async move |notification: SessionNotification, _cx| {
+                    match notification.update {
+                        SessionUpdate::AgentMessageChunk(ContentChunk {
+                            content: ContentBlock::Text(text),
+                            ..
+                        }) => output.lock().unwrap().push_str(&text.text),
+                        SessionUpdate::Plan(plan) => plans.lock().unwrap().push(
+                            plan.entries
+                                .iter()
+                                .map(|entry| {
+                                    format!(
+                                        "{} ({:?}, {:?})",
+                                        entry.content, entry.priority, entry.status
+                                    )
+                                })
+                                .collect(),
+                        ),
+                        _ => {}
+                    }
+                    Ok(())
+                }
+            },
+            sacp::on_receive_notification!(),
+        )
+        .connect_to(conductor())?
+        .run_until(async |cx| {
+            cx.send_request(InitializeRequest::new(ProtocolVersion::LATEST))
+                .block_task()
+                .await?;
+
+            let mut session = cx
+                .build_session(PathBuf::from("."))
+                .block_task()
+                .start_session()
+                .await?;
+
+            session.send_prompt(script)?;
+            session.read_to_string().await
+        })
+        .await?;
+
+    let output = std::mem::take(&mut *output.lock().unwrap());
+    let plans = std::mem::take(&mut *plans.lock().unwrap());
+    Ok((output, plans))
+}
+
+#[tokio::test]
+async fn test_entry_updates_resend_the_whole_plan() -> Result<(), sacp::Error> {
+    let (_, plans) = prompt_with_plans(
+        r#"
+        plan::set([
+            #{ content: "Read the code", priority: "high" },
+            #{ content: "Write the fix" },
+            #{ content: "Tidy up", priority: "low", status: "in_progress" },
+        ]);
+        plan::start(0);
+        plan::complete(0);
+        "#,
+    )
+    .await?;
+
+    expect_test::expect![[r#"
+        [
+            [
+                "Read the code (High, Pending)",
+                "Write the fix (Medium, Pending)",
+                "Tidy up (Low, InProgress)",
+            ],
+            [
+                "Read the code (High, InProgress)",
+                "Write the fix (Medium, Pending)",
+                "Tidy up (Low, InProgress)",
+            ],
+            [
+                "Read the code (High, Completed)",
+                "Write the fix (Medium, Pending)",
+                "Tidy up (Low, InProgress)",
+            ],
+        ]
+    "#]]
+    .assert_debug_eq(&plans);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_invalid_plans_throw() -> Result<(), sacp::Error> {
+    let (output, plans) = prompt_with_plans(
+        r#"
+        try {
+            plan::set([#{ content: "Ship it", status: "done" }]);
+        } catch (err) {
+            say(err + "\n");
+        }
+        plan::set([#{ content: "Ship it" }]);
+        for index in [1, -1] {
+            try {
+                plan::complete(index);
+            } catch (err) {
+                say(err + "\n");
+            }
+        }
+        try {
+            set([]);
+        } catch (err) {
+            say(err.message);
+        }
+        "#,
+    )
+    .await?;
+
+    expect_test::expect![[r#"
+        "Unknown plan entry status: done\nThe plan has no entry 1\nThe plan has no entry -1\nFunction not found: set (array)"
+    "#]]
+    .assert_debug_eq(&output);
+    assert_eq!(plans, vec![vec!["Ship it (Medium, Pending)".to_string()]]);
+
+    Ok(())
+}
@@ -0,0 +1,194 @@
+//! Integration tests for `process::exec`.
+//!
+//! These tests run standard Unix tools, which the agent is configured to allow.
+
+#![cfg(unix)]
+
+use rhaicp::RhaiAgent;
+use sacp::link::AgentToClient;
+use sacp::schema::{InitializeRequest, ProtocolVersion, SessionNotification, SessionUpdate};
+use sacp::{ClientToAgent, Component};
+use sacp_conductor::{Conductor, ProxiesAndAgent};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Wrapper to make RhaiAgent work with the test infrastructure
+struct TestRhaiAgent;
+
+impl Component<AgentToClient> for TestRhaiAgent {
+    async fn serve(
+        self,
+        client: impl Component<sacp::link::ClientToAgent>,
+    ) -> Result<(), sacp::Error> {
+        let agent = RhaiAgent::new().with_allowed_commands(["cat", "sh", "sleep"]);
+        Component::<AgentToClient>::serve(agent, client).await
+    }
+}
+
+fn conductor() -> impl sacp::Component<sacp::link::AgentToClient> {
+    Conductor::new_agent(
+        "test-conductor".to_string(),
+        ProxiesAndAgent::new(TestRhaiAgent),
+        Default::default(),
+    )
+}
+
+#[tokio::test]
+async fn test_output_and_exit_code_are_returned() -> Result<(), sacp::Error> {
+    let result = yopo::prompt(
+        conductor(),
+        r#"
+        let result = process::exec("cat", [], #{ stdin: "piped in" });
+        say("cat: " + result.exit_code + " " + result.stdout + "\n");
+        let result = process::exec("sh", ["-c", "echo $GREETING; echo oops >&2; exit 4"], #{
+            env: #{ GREETING: "hello" },
+        });
+        say("sh: " + result.exit_code + " " + result.stdout + result.stderr);
+        "#,
+    )
+    .await?;
+
+    expect_test::expect![[r#"
+        "cat: 0 piped in\nsh: 4 hello\noops\n"
+    "#]]
+    .assert_debug_eq(&result);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_disallowed_and_slow_commands_throw() -> Result<(), sacp::Error> {
+    let result = yopo::prompt(
+        conductor(),
+        r#"
+        try {
+            process::exec("rm", ["-rf", "target"]);
+        } catch (err) {
+            say(err + "\n");
+        }
+        try {
+            process::exec("sleep", ["10"], #{ timeout_ms: 100 });
+        } catch (err) {
+            say(err + "\n");
+        }
+        try {
+            exec("sh", ["-c", "echo global"]);
+        } catch (err) {
+            say(err.message);
+        }
+        "#,
+    )
+    .await?;
+
+    expect_test::expect![[r#"
+        "rm is not an allowed command\nFailed to run sleep: timed out after 100 ms\nFunction not found: exec (&str | ImmutableString | String, array)"
+    "#]]
+    .assert_debug_eq(&result);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_scripts_cannot_swap_in_another_program() -> Result<(), sacp::Error> {
+    // A fake `sh` on a script-set PATH must not run in place of the real one
+    let fake_dir = std::env::temp_dir().join(format!("rhaicp-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&fake_dir).unwrap();
+    let fake_sh = fake_dir.join("sh");
+    std::fs::write(&fake_sh, "#!/bin/sh\necho fake\n").unwrap();
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&fake_sh, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    let result = yopo::prompt(
+        conductor(),
+        &format!(
+            r#"
+            for name in ["PATH", "LD_PRELOAD", "DYLD_INSERT_LIBRARIES"] {{
+                try {{
+                    let env = #{{}};
+                    env[name] = "{dir}";
+                    process::exec("sh", ["-c", "echo real"], #{{ env: env }});
+                }} catch (err) {{
+                    say(err + "\n");
+                }}
+            }}
+            say(process::exec("sh", ["-c", "echo real"]).stdout);
+            "#,
+            dir = fake_dir.display()
+        ),
+    )
+    .await?;
+
+    expect_test::expect![[r#"
+        "process::exec cannot set PATH\nprocess::exec cannot set LD_PRELOAD\nprocess::exec cannot set DYLD_INSERT_LIBRARIES\nreal\n"
+    "#]]
+    .assert_debug_eq(&result);
+
+    let _ = std::fs::remove_dir_all(&fake_dir);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_processes_are_reported_as_execute_tool_calls() -> Result<(), sacp::Error> {
+    let updates = Arc::new(Mutex::new(Vec::new()));
+    ClientToAgent::builder()
+        .on_receive_notification(
+            {
+                let updates = updates.clone();
+                async move |notification: SessionNotification, _cx| {
+                    updates.lock().unwrap().push(notification.update);
+                    Ok(())
+                }
+            },
+            sacp::on_receive_notification!(),
+        )
+        .connect_to(conductor())?
+        .run_until(async |cx| {
+            cx.send_request(InitializeRequest::new(ProtocolVersion::LATEST))
+                .block_task()
+                .await?;
+
+            let mut session = cx
+                .build_session(PathBuf::from("."))
+                .block_task()
+                .start_session()
+                .await?;
+
+            session.send_prompt(r#"process::exec("sh", ["-c", "echo done"]);"#)?;
+            session.read_to_string().await
+        })
+        .await?;
+
+    let descriptions: Vec<_> = updates
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(|update| match update {
+            SessionUpdate::ToolCall(call) => Some(format!(
+                "call {} {:?} {:?}",
+                call.title, call.kind, call.status
+            )),
+            SessionUpdate::ToolCallUpdate(update) => Some(format!(
+                "update {:?} {:?}",
+                update.fields.status,
+                update
+                    .fields
+                    .raw_output
+                    .as_ref()
+                    .map(|output| &output["stdout"])
+            )),
+            _ => None,
+        })
+        .collect();
+    expect_test::expect![[r#"
+        [
+            "call sh -c echo done Execute Pending",
+            "update Some(InProgress) None",
+            "update Some(Completed) Some(String(\"done\\n\"))",
+        ]
+    "#]]
+    .assert_debug_eq(&descriptions);
+
+    Ok(())
+}
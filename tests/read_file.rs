@@ -0,0 +1,147 @@
+//! Integration tests for `read_file`.
+
+use rhaicp::{FsPolicy, RhaiAgent};
+use sacp::link::AgentToClient;
+use sacp::schema::{
+    ClientCapabilities, FileSystemCapability, InitializeRequest, ProtocolVersion,
+    ReadTextFileRequest, ReadTextFileResponse,
+};
+use sacp::{ClientToAgent, Component};
+use sacp_conductor::{Conductor, ProxiesAndAgent};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Wrapper to make RhaiAgent work with the test infrastructure
+struct TestRhaiAgent;
+
+impl Component<AgentToClient> for TestRhaiAgent {
+    async fn serve(
+        self,
+        client: impl Component<sacp::link::ClientToAgent>,
+    ) -> Result<(), sacp::Error> {
+        // The tests keep their files in the system temp directory
+        let agent =
+            RhaiAgent::new().with_fs_policy(FsPolicy::new().allow_root(std::env::temp_dir()));
+        Component::<AgentToClient>::serve(agent, client).await
+    }
+}
+
+fn conductor() -> impl sacp::Component<sacp::link::AgentToClient> {
+    Conductor::new_agent(
+        "test-conductor".to_string(),
+        ProxiesAndAgent::new(TestRhaiAgent),
+        Default::default(),
+    )
+}
+
+/// Create a file with three lines in a fresh temporary directory
+fn three_line_file() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rhaicp-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("lines.txt");
+    std::fs::write(&path, "one\ntwo\nthree\n").unwrap();
+    path
+}
+
+#[tokio::test]
+async fn test_read_local_file() -> Result<(), sacp::Error> {
+    let path = three_line_file();
+
+    let result = yopo::prompt(
+        conductor(),
+        &format!(
+            r#"
+            let path = "{}";
+            say(read_file(path));
+            say("--\n");
+            say(read_file(path, #{{ line: 2 }}));
+            say("--\n");
+            say(read_file(path, #{{ line: 2, limit: 1 }}));
+            "#,
+            path.display()
+        ),
+    )
+    .await?;
+
+    expect_test::expect![[r#"
+        "one\ntwo\nthree\n--\ntwo\nthree\n--\ntwo\n"
+    "#]]
+    .assert_debug_eq(&result);
+
+    let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_read_errors_can_be_caught() -> Result<(), sacp::Error> {
+    let result = yopo::prompt(
+        conductor(),
+        r#"
+        try {
+            read_file("target/no-such-file.txt");
+        } catch (err) {
+            say((err.starts_with("Failed to read ") && err.contains("target/no-such-file.txt")) + "\n");
+        }
+        try {
+            read_file("target/no-such-file.txt", #{ line: 0 });
+        } catch (err) {
+            say(err);
+        }
+        "#,
+    )
+    .await?;
+
+    expect_test::expect![[r#"
+        "true\nline must be a positive integer"
+    "#]]
+    .assert_debug_eq(&result);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_reads_go_through_client_that_can_read_files() -> Result<(), sacp::Error> {
+    let reads = Arc::new(Mutex::new(Vec::new()));
+    let result = ClientToAgent::builder()
+        .on_receive_request(
+            {
+                let reads = reads.clone();
+                async move |request: ReadTextFileRequest, request_cx, _cx| {
+                    reads
+                        .lock()
+                        .unwrap()
+                        .push((request.path, request.line, request.limit));
+                    request_cx.respond(ReadTextFileResponse::new("unsaved buffer"))
+                }
+            },
+            sacp::on_receive_request!(),
+        )
+        .connect_to(conductor())?
+        .run_until(async |cx| {
+            cx.send_request(
+                InitializeRequest::new(ProtocolVersion::LATEST).client_capabilities(
+                    ClientCapabilities::new().fs(FileSystemCapability::new().read_text_file(true)),
+                ),
+            )
+            .block_task()
+            .await?;
+
+            let mut session = cx
+                .build_session(PathBuf::from("/work"))
+                .block_task()
+                .start_session()
+                .await?;
+
+            session.send_prompt(r#"say(read_file("/work/notes.txt", #{ line: 3, limit: 2 }));"#)?;
+            session.read_to_string().await
+        })
+        .await?;
+
+    assert_eq!(result, "unsaved buffer");
+    assert_eq!(
+        *reads.lock().unwrap(),
+        vec![(PathBuf::from("/work/notes.txt"), Some(3), Some(2))]
+    );
+
+    Ok(())
+}
@@ -0,0 +1,98 @@
+//! Integration tests for resolving script file paths against the session cwd.
+
+use rhaicp::RhaiAgent;
+use sacp::link::AgentToClient;
+use sacp::schema::{InitializeRequest, ProtocolVersion};
+use sacp::{ClientToAgent, Component};
+use sacp_conductor::{Conductor, ProxiesAndAgent};
+use std::path::{Path, PathBuf};
+
+/// Wrapper to make RhaiAgent work with the test infrastructure
+struct TestRhaiAgent;
+
+impl Component<AgentToClient> for TestRhaiAgent {
+    async fn serve(
+        self,
+        client: impl Component<sacp::link::ClientToAgent>,
+    ) -> Result<(), sacp::Error> {
+        Component::<AgentToClient>::serve(RhaiAgent::new(), client).await
+    }
+}
+
+fn conductor() -> impl sacp::Component<sacp::link::AgentToClient> {
+    Conductor::new_agent(
+        "test-conductor".to_string(),
+        ProxiesAndAgent::new(TestRhaiAgent),
+        Default::default(),
+    )
+}
+
+/// A fresh temporary directory to use as the session cwd
+fn temp_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rhaicp-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Run `script` in a session whose cwd is `cwd`
+async fn prompt_in(cwd: &Path, script: &str) -> Result<String, sacp::Error> {
+    ClientToAgent::builder()
+        .connect_to(conductor())?
+        .run_until(async |cx| {
+            cx.send_request(InitializeRequest::new(ProtocolVersion::LATEST))
+                .block_task()
+                .await?;
+
+            let mut session = cx.build_session(cwd).block_task().start_session().await?;
+
+            session.send_prompt(script)?;
+            session.read_to_string().await
+        })
+        .await
+}
+
+#[tokio::test]
+async fn test_relative_paths_use_session_cwd() -> Result<(), sacp::Error> {
+    let cwd = temp_dir();
+
+    let result = prompt_in(
+        &cwd,
+        r#"
+        write_file("notes.txt", "kept in the session cwd");
+        say(read_file("notes.txt"));
+        "#,
+    )
+    .await?;
+
+    assert_eq!(result, "kept in the session cwd");
+    assert_eq!(
+        std::fs::read_to_string(cwd.join("notes.txt")).unwrap(),
+        "kept in the session cwd"
+    );
+
+    let _ = std::fs::remove_dir_all(&cwd);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cwd_is_a_read_only_variable() -> Result<(), sacp::Error> {
+    let cwd = temp_dir();
+
+    let result = prompt_in(
+        &cwd,
+        r#"
+        say(CWD + "\n");
+        try {
+            CWD = "/";
+        } catch (err) {
+            say("cannot assign");
+        }
+        "#,
+    )
+    .await?;
+
+    assert_eq!(result, format!("{}\ncannot assign", cwd.display()));
+
+    let _ = std::fs::remove_dir_all(&cwd);
+    Ok(())
+}
@@ -0,0 +1,112 @@
+//! Integration tests for Rhai state that persists across prompts in a session.
+
+use rhaicp::RhaiAgent;
+use sacp::link::AgentToClient;
+use sacp::schema::{InitializeRequest, ProtocolVersion};
+use sacp::{ClientToAgent, Component};
+use sacp_conductor::{Conductor, ProxiesAndAgent};
+use std::path::PathBuf;
+
+/// Wrapper to make RhaiAgent work with the test infrastructure
+struct TestRhaiAgent;
+
+impl Component<AgentToClient> for TestRhaiAgent {
+    async fn serve(
+        self,
+        client: impl Component<sacp::link::ClientToAgent>,
+    ) -> Result<(), sacp::Error> {
+        Component::<AgentToClient>::serve(RhaiAgent::new(), client).await
+    }
+}
+
+fn conductor() -> impl sacp::Component<sacp::link::AgentToClient> {
+    Conductor::new_agent(
+        "test-conductor".to_string(),
+        ProxiesAndAgent::new(TestRhaiAgent),
+        Default::default(),
+    )
+}
+
+/// Send each prompt in turn within a single session, collecting the text of each response
+async fn prompt_session(prompts: &[&str]) -> Result<Vec<String>, sacp::Error> {
+    ClientToAgent::builder()
+        .connect_to(conductor())?
+        .run_until(async |cx| {
+            cx.send_request(InitializeRequest::new(ProtocolVersion::LATEST))
+                .block_task()
+                .await?;
+
+            let mut session = cx
+                .build_session(PathBuf::from("."))
+                .block_task()
+                .start_session()
+                .await?;
+
+            let mut outputs = vec![];
+            for prompt in prompts {
+                session.send_prompt(prompt)?;
+                outputs.push(session.read_to_string().await?);
+            }
+            Ok(outputs)
+        })
+        .await
+}
+
+#[tokio::test]
+async fn test_variables_persist_across_prompts() -> Result<(), sacp::Error> {
+    let outputs = prompt_session(&[
+        r#"let count = 1;"#,
+        r#"count += 41; say(count.to_string());"#,
+    ])
+    .await?;
+
+    expect_test::expect![[r#"
+        [
+            "",
+            "42",
+        ]
+    "#]]
+    .assert_debug_eq(&outputs);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_functions_persist_across_prompts() -> Result<(), sacp::Error> {
+    let outputs = prompt_session(&[
+        r#"fn greet(name) { "Hello, " + name + "!" }"#,
+        r#"say(greet("Rhai"));"#,
+    ])
+    .await?;
+
+    expect_test::expect![[r#"
+        [
+            "",
+            "Hello, Rhai!",
+        ]
+    "#]]
+    .assert_debug_eq(&outputs);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_reset_discards_state() -> Result<(), sacp::Error> {
+    let outputs = prompt_session(&[
+        r#"let count = 1; fn greet() { "hi" }"#,
+        r#"reset();"#,
+        r#"say(is_def_var("count").to_string() + " " + is_def_fn("greet", 0).to_string());"#,
+    ])
+    .await?;
+
+    expect_test::expect![[r#"
+        [
+            "",
+            "",
+            "false false",
+        ]
+    "#]]
+    .assert_debug_eq(&outputs);
+
+    Ok(())
+}
@@ -0,0 +1,259 @@
+//! Integration tests for `terminal::run`.
+//!
+//! The client in these tests pretends to run every command, which prints
+//! `running tests` and exits with status 3.
+
+use rhaicp::RhaiAgent;
+use sacp::link::AgentToClient;
+use sacp::schema::{
+    ClientCapabilities, ContentBlock, ContentChunk, CreateTerminalRequest, CreateTerminalResponse,
+    InitializeRequest, ProtocolVersion, ReleaseTerminalRequest, ReleaseTerminalResponse,
+    SessionNotification, SessionUpdate, TerminalExitStatus, TerminalOutputRequest,
+    TerminalOutputResponse, ToolCallContent, WaitForTerminalExitRequest,
+    WaitForTerminalExitResponse,
+};
+use sacp::{ClientToAgent, Component};
+use sacp_conductor::{Conductor, ProxiesAndAgent};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Wrapper to make RhaiAgent work with the test infrastructure
+struct TestRhaiAgent;
+
+impl Component<AgentToClient> for TestRhaiAgent {
+    async fn serve(
+        self,
+        client: impl Component<sacp::link::ClientToAgent>,
+    ) -> Result<(), sacp::Error> {
+        Component::<AgentToClient>::serve(RhaiAgent::new(), client).await
+    }
+}
+
+fn conductor() -> impl sacp::Component<sacp::link::AgentToClient> {
+    Conductor::new_agent(
+        "test-conductor".to_string(),
+        ProxiesAndAgent::new(TestRhaiAgent),
+        Default::default(),
+    )
+}
+
+/// What the client saw of a prompt
+#[derive(Default)]
+struct Transcript {
+    /// Text sent with `say`
+    output: String,
+    /// Tool-call notifications, summarized
+    tool_calls: Vec<String>,
+    /// Terminal requests, summarized
+    requests: Vec<String>,
+}
+
+/// Run `script` in a session with cwd `/work`, with a client that advertises
+/// terminals if `terminals` is set
+async fn prompt_with_terminals(terminals: bool, script: &str) -> Result<Transcript, sacp::Error> {
+    let transcript = Arc::new(Mutex::new(Transcript::default()));
+
+    ClientToAgent::builder()
+        .on_receive_notification(
+            {
+                let transcript = transcript.clone();
+                async move |notification: SessionNotification, _cx| {
+                    let mut transcript = transcript.lock().unwrap();
+                    match notification.update {
+                        SessionUpdate::AgentMessageChunk(ContentChunk {
+                            content: ContentBlock::Text(text),
+                            ..
+                        }) => transcript.output.push_str(&text.text),
+                        SessionUpdate::ToolCall(call) => transcript.tool_calls.push(format!(
+                            "call {} {:?} {:?}",
+                            call.title, call.kind, call.status
+                        )),
+                        SessionUpdate::ToolCallUpdate(update) => {
+                            let terminals: Vec<_> = update
+                                .fields
+                                .content
+                                .iter()
+                                .flatten()
+                                .filter_map(|content| match content {
+                                    ToolCallContent::Terminal(terminal) => {
+                                        Some(terminal.terminal_id.0.to_string())
+                                    }
+                                    _ => None,
+                                })
+                                .collect();
+                            transcript
+                                .tool_calls
+                                .push(format!("update {:?} {:?}", update.fields.status, terminals));
+                        }
+                        _ => {}
+                    }
+                    Ok(())
+                }
+            },
+            sacp::on_receive_notification!(),
+        )
+        .on_receive_request(
+            {
+                let transcript = transcript.clone();
+                async move |request: CreateTerminalRequest, request_cx, _cx| {
+                    let env: Vec<_> = request
+                        .env
+                        .iter()
+                        .map(|var| format!("{}={}", var.name, var.value))
+                        .collect();
+                    transcript.lock().unwrap().requests.push(format!(
+                        "create {} {:?} in {} with {:?}, limit {:?}",
+                        request.command,
+                        request.args,
+                        request.cwd.unwrap_or_default().display(),
+                        env,
+                        request.output_byte_limit
+                    ));
+                    request_cx.respond(CreateTerminalResponse::new("term-1"))
+                }
+            },
+            sacp::on_receive_request!(),
+        )
+        .on_receive_request(
+            {
+                let transcript = transcript.clone();
+                async move |request: WaitForTerminalExitRequest, request_cx, _cx| {
+                    transcript
+                        .lock()
+                        .unwrap()
+                        .requests
+                        .push(format!("wait {}", request.terminal_id.0));
+                    request_cx.respond(WaitForTerminalExitResponse::new(
+                        TerminalExitStatus::new().exit_code(3),
+                    ))
+                }
+            },
+            sacp::on_receive_request!(),
+        )
+        .on_receive_request(
+            {
+                let transcript = transcript.clone();
+                async move |request: TerminalOutputRequest, request_cx, _cx| {
+                    transcript
+                        .lock()
+                        .unwrap()
+                        .requests
+                        .push(format!("output {}", request.terminal_id.0));
+                    request_cx.respond(
+                        TerminalOutputResponse::new("running tests\n", false)
+                            .exit_status(TerminalExitStatus::new().exit_code(3)),
+                    )
+                }
+            },
+            sacp::on_receive_request!(),
+        )
+        .on_receive_request(
+            {
+                let transcript = transcript.clone();
+                async move |request: ReleaseTerminalRequest, request_cx, _cx| {
+                    transcript
+                        .lock()
+                        .unwrap()
+                        .requests
+                        .push(format!("release {}", request.terminal_id.0));
+                    request_cx.respond(ReleaseTerminalResponse::new())
+                }
+            },
+            sacp::on_receive_request!(),
+        )
+        .connect_to(conductor())?
+        .run_until(async |cx| {
+            cx.send_request(
+                InitializeRequest::new(ProtocolVersion::LATEST)
+                    .client_capabilities(ClientCapabilities::new().terminal(terminals)),
+            )
+            .block_task()
+            .await?;
+
+            let mut session = cx
+                .build_session(PathBuf::from("/work"))
+                .block_task()
+                .start_session()
+                .await?;
+
+            session.send_prompt(script)?;
+            session.read_to_string().await
+        })
+        .await?;
+
+    let transcript = std::mem::take(&mut *transcript.lock().unwrap());
+    Ok(transcript)
+}
+
+#[tokio::test]
+async fn test_commands_run_in_client_terminals() -> Result<(), sacp::Error> {
+    let transcript = prompt_with_terminals(
+        true,
+        r#"
+        let result = terminal::run("cargo", ["test", "--quiet"], #{
+            cwd: "crate",
+            env: #{ RUST_LOG: "debug" },
+            output_byte_limit: 1000,
+        });
+        say("exit_code: " + result.exit_code + "\n");
+        say("signal: " + type_of(result.signal) + "\n");
+        say("output: " + result.output);
+        say("truncated: " + result.truncated);
+        "#,
+    )
+    .await?;
+
+    expect_test::expect![[r#"
+        "exit_code: 3\nsignal: ()\noutput: running tests\ntruncated: false"
+    "#]]
+    .assert_debug_eq(&transcript.output);
+    expect_test::expect![[r#"
+        [
+            "create cargo [\"test\", \"--quiet\"] in /work/crate with [\"RUST_LOG=debug\"], limit Some(1000)",
+            "wait term-1",
+            "output term-1",
+            "release term-1",
+        ]
+    "#]]
+    .assert_debug_eq(&transcript.requests);
+    expect_test::expect![[r#"
+        [
+            "call cargo test --quiet Execute Pending",
+            "update Some(InProgress) [\"term-1\"]",
+            "update Some(Failed) []",
+        ]
+    "#]]
+    .assert_debug_eq(&transcript.tool_calls);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_clients_without_terminals_throw() -> Result<(), sacp::Error> {
+    let transcript = prompt_with_terminals(
+        false,
+        r#"
+        try {
+            terminal::run("cargo", ["build"]);
+        } catch (err) {
+            say(err + "\n");
+        }
+        try {
+            run("cargo", ["build"]);
+        } catch (err) {
+            say(err.message);
+        }
+        "#,
+    )
+    .await?;
+
+    // `run` is only reachable through the module
+    assert_eq!(
+        transcript.output,
+        "The client does not support terminals\n\
+         Function not found: run (&str | ImmutableString | String, array)"
+    );
+    assert!(transcript.requests.is_empty());
+
+    Ok(())
+}
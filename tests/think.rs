@@ -0,0 +1,94 @@
+//! Integration test for streaming thoughts with think().
+
+use rhaicp::RhaiAgent;
+use sacp::link::AgentToClient;
+use sacp::schema::{
+    ContentBlock, ContentChunk, InitializeRequest, ProtocolVersion, SessionNotification,
+    SessionUpdate,
+};
+use sacp::{ClientToAgent, Component};
+use sacp_conductor::{Conductor, ProxiesAndAgent};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Wrapper to make RhaiAgent work with the test infrastructure
+struct TestRhaiAgent;
+
+impl Component<AgentToClient> for TestRhaiAgent {
+    async fn serve(
+        self,
+        client: impl Component<sacp::link::ClientToAgent>,
+    ) -> Result<(), sacp::Error> {
+        Component::<AgentToClient>::serve(RhaiAgent::new(), client).await
+    }
+}
+
+fn conductor() -> impl sacp::Component<sacp::link::AgentToClient> {
+    Conductor::new_agent(
+        "test-conductor".to_string(),
+        ProxiesAndAgent::new(TestRhaiAgent),
+        Default::default(),
+    )
+}
+
+#[tokio::test]
+async fn test_thoughts_are_sent_apart_from_messages() -> Result<(), sacp::Error> {
+    let chunks = Arc::new(Mutex::new(Vec::new()));
+    ClientToAgent::builder()
+        .on_receive_notification(
+            {
+                let chunks = chunks.clone();
+                async move |notification: SessionNotification, _cx| {
+                    let chunk = match notification.update {
+                        SessionUpdate::AgentMessageChunk(ContentChunk {
+                            content: ContentBlock::Text(text),
+                            ..
+                        }) => format!("message: {}", text.text),
+                        SessionUpdate::AgentThoughtChunk(ContentChunk {
+                            content: ContentBlock::Text(text),
+                            ..
+                        }) => format!("thought: {}", text.text),
+                        _ => return Ok(()),
+                    };
+                    chunks.lock().unwrap().push(chunk);
+                    Ok(())
+                }
+            },
+            sacp::on_receive_notification!(),
+        )
+        .connect_to(conductor())?
+        .run_until(async |cx| {
+            cx.send_request(InitializeRequest::new(ProtocolVersion::LATEST))
+                .block_task()
+                .await?;
+
+            let mut session = cx
+                .build_session(PathBuf::from("."))
+                .block_task()
+                .start_session()
+                .await?;
+
+            session.send_prompt(
+                r#"
+                think("Adding up...");
+                let total = 0;
+                for n in 1..=4 { total += n; }
+                think("Done");
+                say("The total is " + total);
+                "#,
+            )?;
+            session.read_to_string().await
+        })
+        .await?;
+
+    expect_test::expect![[r#"
+        [
+            "thought: Adding up...",
+            "thought: Done",
+            "message: The total is 10",
+        ]
+    "#]]
+    .assert_debug_eq(&*chunks.lock().unwrap());
+
+    Ok(())
+}
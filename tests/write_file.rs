@@ -1,9 +1,16 @@
 //! Integration test for rhaicp basic say() functionality.
 
-use rhaicp::RhaiAgent;
-use sacp::Component;
+use rhaicp::{FsPolicy, RhaiAgent};
 use sacp::link::AgentToClient;
+use sacp::schema::{
+    ClientCapabilities, FileSystemCapability, InitializeRequest, ProtocolVersion,
+    SessionNotification, SessionUpdate, ToolCallContent, WriteTextFileRequest,
+    WriteTextFileResponse,
+};
+use sacp::{ClientToAgent, Component};
 use sacp_conductor::{Conductor, ProxiesAndAgent};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 
 /// Wrapper to make RhaiAgent work with the test infrastructure
 struct TestRhaiAgent;
@@ -13,7 +20,10 @@ impl Component<AgentToClient> for TestRhaiAgent {
         self,
         client: impl Component<sacp::link::ClientToAgent>,
     ) -> Result<(), sacp::Error> {
-        Component::<AgentToClient>::serve(RhaiAgent::new(), client).await
+        // The tests keep their files in the system temp directory
+        let agent =
+            RhaiAgent::new().with_fs_policy(FsPolicy::new().allow_root(std::env::temp_dir()));
+        Component::<AgentToClient>::serve(agent, client).await
     }
 }
 
@@ -40,3 +50,198 @@ async fn test_say_hello_world() -> Result<(), sacp::Error> {
 
     Ok(())
 }
+
+/// Summarize a tool-call update for comparison, replacing `dir` with `<dir>`
+fn describe_update(dir: &str, update: &SessionUpdate) -> Option<String> {
+    let describe_content = |content: &[ToolCallContent]| {
+        content
+            .iter()
+            .map(|content| match content {
+                ToolCallContent::Diff(diff) => format!(
+                    "diff {} -> {}",
+                    diff.old_text.as_deref().unwrap_or("<none>"),
+                    diff.new_text
+                ),
+                other => format!("{:?}", other),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let description = match update {
+        SessionUpdate::ToolCall(call) => format!(
+            "call {}: {} {:?} {:?} {}",
+            call.tool_call_id.0,
+            call.title,
+            call.kind,
+            call.status,
+            describe_content(&call.content),
+        ),
+        SessionUpdate::ToolCallUpdate(update) => format!(
+            "update {}: {:?}",
+            update.tool_call_id.0, update.fields.status
+        ),
+        _ => return None,
+    };
+    Some(description.replace(dir, "<dir>"))
+}
+
+#[tokio::test]
+async fn test_writes_are_reported_as_edits() -> Result<(), sacp::Error> {
+    let dir = std::env::temp_dir().join(format!("rhaicp-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("notes.txt");
+    // Files that are not text cannot be shown as a diff
+    let binary_path = dir.join("data.bin");
+    std::fs::write(&binary_path, [0xff, 0xfe, 0x00]).unwrap();
+
+    let updates = Arc::new(Mutex::new(Vec::new()));
+    ClientToAgent::builder()
+        .on_receive_notification(
+            {
+                let updates = updates.clone();
+                async move |notification: SessionNotification, _cx| {
+                    updates.lock().unwrap().push(notification.update);
+                    Ok(())
+                }
+            },
+            sacp::on_receive_notification!(),
+        )
+        .connect_to(conductor())?
+        .run_until(async |cx| {
+            cx.send_request(InitializeRequest::new(ProtocolVersion::LATEST))
+                .block_task()
+                .await?;
+
+            let mut session = cx
+                .build_session(PathBuf::from("."))
+                .block_task()
+                .start_session()
+                .await?;
+
+            session.send_prompt(format!(
+                r#"
+                write_file("{path}", "first");
+                write_file("{path}", "second");
+                write_file("{binary_path}", "third");
+                "#,
+                path = path.display(),
+                binary_path = binary_path.display()
+            ))?;
+            session.read_to_string().await
+        })
+        .await?;
+
+    let updates = updates.lock().unwrap();
+    let ids: Vec<_> = updates
+        .iter()
+        .filter_map(|update| match update {
+            SessionUpdate::ToolCall(call) => Some(call.tool_call_id.clone()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(ids.len(), 3);
+    assert_ne!(ids[0], ids[1], "each write should get its own tool call id");
+
+    let dir = dir.display().to_string();
+    let descriptions: Vec<_> = updates
+        .iter()
+        .filter_map(|update| describe_update(&dir, update))
+        .map(|description| {
+            description
+                .replace(&*ids[0].0, "<first>")
+                .replace(&*ids[1].0, "<second>")
+                .replace(&*ids[2].0, "<third>")
+        })
+        .collect();
+    expect_test::expect![[r#"
+        [
+            "call <first>: Write <dir>/notes.txt Edit Pending diff <none> -> first",
+            "update <first>: Some(InProgress)",
+            "update <first>: Some(Completed)",
+            "call <second>: Write <dir>/notes.txt Edit Pending diff first -> second",
+            "update <second>: Some(InProgress)",
+            "update <second>: Some(Completed)",
+            "call <third>: Write <dir>/data.bin Edit Pending ",
+            "update <third>: Some(InProgress)",
+            "update <third>: Some(Completed)",
+        ]
+    "#]]
+    .assert_debug_eq(&descriptions);
+
+    let _ = std::fs::remove_dir_all(&dir);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_write_failure_can_be_caught() -> Result<(), sacp::Error> {
+    let result = yopo::prompt(
+        conductor(),
+        r#"
+        write_file("target/write-file-test.txt", "ok");
+        say("first write succeeded\n");
+        try {
+            write_file("target/no-such-dir/test.txt", "fn main() {}");
+            say("unreachable");
+        } catch (err) {
+            say("caught: " + (err.starts_with("Failed to write ") && err.contains("target/no-such-dir/test.txt")));
+        }
+        "#,
+    )
+    .await?;
+
+    expect_test::expect![[r#"
+        "first write succeeded\ncaught: true"
+    "#]]
+    .assert_debug_eq(&result);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_writes_go_through_client_that_can_write_files() -> Result<(), sacp::Error> {
+    let path = std::env::temp_dir().join(format!("rhaicp-test-{}.txt", uuid::Uuid::new_v4()));
+
+    let writes = Arc::new(Mutex::new(Vec::new()));
+    ClientToAgent::builder()
+        .on_receive_request(
+            {
+                let writes = writes.clone();
+                async move |request: WriteTextFileRequest, request_cx, _cx| {
+                    writes.lock().unwrap().push((request.path, request.content));
+                    request_cx.respond(WriteTextFileResponse::new())
+                }
+            },
+            sacp::on_receive_request!(),
+        )
+        .connect_to(conductor())?
+        .run_until(async |cx| {
+            cx.send_request(
+                InitializeRequest::new(ProtocolVersion::LATEST).client_capabilities(
+                    ClientCapabilities::new().fs(FileSystemCapability::new().write_text_file(true)),
+                ),
+            )
+            .block_task()
+            .await?;
+
+            let mut session = cx
+                .build_session(PathBuf::from("."))
+                .block_task()
+                .start_session()
+                .await?;
+
+            session.send_prompt(format!(
+                r#"write_file("{}", "from the editor");"#,
+                path.display()
+            ))?;
+            session.read_to_string().await
+        })
+        .await?;
+
+    assert_eq!(
+        *writes.lock().unwrap(),
+        vec![(path.clone(), "from the editor".to_string())]
+    );
+    assert!(!path.exists(), "the agent should not write the file itself");
+
+    Ok(())
+}